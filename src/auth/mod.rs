@@ -0,0 +1,218 @@
+use crate::model::AppError;
+use async_graphql::{Context, Guard};
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+
+/// Claims carried by the HS256 bearer tokens the service accepts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Claims {
+    pub sub: String,
+    pub exp: usize,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// Verifies bearer tokens; registered as a request extension.
+#[derive(Clone)]
+pub(crate) struct JwtKeys {
+    decoding: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtKeys {
+    pub fn new(secret: &[u8]) -> Self {
+        JwtKeys {
+            decoding: DecodingKey::from_secret(secret),
+            validation: Validation::new(Algorithm::HS256),
+        }
+    }
+
+    fn verify(&self, token: &str) -> Authentication {
+        match jsonwebtoken::decode::<Claims>(token, &self.decoding, &self.validation) {
+            Ok(data) => Authentication::Authenticated(data.claims),
+            Err(err) if matches!(err.kind(), ErrorKind::ExpiredSignature) => {
+                Authentication::Rejected("token has expired")
+            }
+            Err(_) => Authentication::Rejected("invalid token"),
+        }
+    }
+}
+
+/// Who sent the request, as far as the `Authorization` header tells.
+///
+/// Extraction never fails: behind `authenticate` bad tokens never get this
+/// far, and elsewhere a bad token only matters to the resolvers that require
+/// authentication, so public fields keep working without one.
+#[derive(Clone, Debug)]
+pub(crate) enum Authentication {
+    Anonymous,
+    Authenticated(Claims),
+    Rejected(&'static str),
+}
+
+impl Authentication {
+    fn from_headers(headers: &HeaderMap, keys: Option<&JwtKeys>) -> Self {
+        match headers.get(header::AUTHORIZATION) {
+            Some(value) => Authentication::from_authorization(value.to_str().unwrap_or(""), keys),
+            None => Authentication::Anonymous,
+        }
+    }
+
+    /// Reads an `Authorization` value, `Bearer <token>`.
+    fn from_authorization(value: &str, keys: Option<&JwtKeys>) -> Self {
+        let token = match value.strip_prefix("Bearer ") {
+            Some(token) => token,
+            None => return Authentication::Rejected("malformed authorization header"),
+        };
+        match keys {
+            Some(keys) => keys.verify(token),
+            None => Authentication::Rejected("authentication is not configured"),
+        }
+    }
+
+    /// Refuses invalid tokens, and missing ones when `required`.
+    fn admit(self, required: bool) -> Result<Self, &'static str> {
+        match self {
+            Authentication::Rejected(reason) => Err(reason),
+            Authentication::Anonymous if required => Err("authentication required"),
+            auth => Ok(auth),
+        }
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Authentication {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        if let Some(auth) = req.extensions().get::<Authentication>() {
+            return Ok(auth.clone());
+        }
+        Ok(Authentication::from_headers(
+            req.headers(),
+            req.extensions().get::<JwtKeys>(),
+        ))
+    }
+}
+
+/// Marks that GraphQL requests need a valid token; registered as a request
+/// extension when `AUTH_REQUIRED` is set.
+#[derive(Clone, Copy)]
+pub(crate) struct AuthRequired;
+
+/// Answers `401` with a JSON body for invalid or expired tokens, and for
+/// requests without one under `AUTH_REQUIRED`; otherwise stores the
+/// `Authentication` for the handler.
+pub(crate) async fn authenticate<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let keys = match req.extensions().get::<JwtKeys>() {
+        Some(keys) => keys.clone(),
+        None => return next.run(req).await,
+    };
+    let auth = Authentication::from_headers(req.headers(), Some(&keys));
+    let required = req.extensions().get::<AuthRequired>().is_some();
+    match auth.admit(required) {
+        Ok(auth) => {
+            req.extensions_mut().insert(auth);
+            next.run(req).await
+        }
+        Err(reason) => unauthorized(reason),
+    }
+}
+
+/// Authenticates a `/ws` connection. Browsers can't set headers on the
+/// upgrade request, so the token may instead come as the `Authorization`
+/// entry of the `connection_init` payload, and `authenticate`'s checks run
+/// once that arrives.
+pub(crate) struct ConnectionAuth {
+    upgrade: Authentication,
+    keys: Option<JwtKeys>,
+    required: bool,
+}
+
+impl ConnectionAuth {
+    /// The connection's `Authentication`, or why it is refused. Without a
+    /// token in `payload` the upgrade request's header counts.
+    pub fn init(self, payload: &Value) -> Result<Authentication, &'static str> {
+        let authorization = payload
+            .get("Authorization")
+            .or_else(|| payload.get("authorization"))
+            .and_then(Value::as_str);
+        let auth = match authorization {
+            Some(value) => Authentication::from_authorization(value, self.keys.as_ref()),
+            None => self.upgrade,
+        };
+        match self.keys {
+            Some(_) => auth.admit(self.required),
+            None => Ok(auth),
+        }
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for ConnectionAuth {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let keys = req.extensions().get::<JwtKeys>().cloned();
+        Ok(ConnectionAuth {
+            upgrade: Authentication::from_headers(req.headers(), keys.as_ref()),
+            keys,
+            required: req.extensions().get::<AuthRequired>().is_some(),
+        })
+    }
+}
+
+fn unauthorized(reason: &'static str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Json(json!({ "error": "unauthorized", "message": reason })),
+    )
+        .into_response()
+}
+
+/// Returns the caller's claims, or an `UNAUTHENTICATED` error explaining why
+/// there are none.
+pub(crate) fn require_claims<'a>(ctx: &'a Context<'_>) -> Result<&'a Claims, AppError> {
+    match ctx.data_opt::<Authentication>() {
+        Some(Authentication::Authenticated(claims)) => Ok(claims),
+        Some(Authentication::Rejected(reason)) => Err(AppError::Unauthorized((*reason).into())),
+        Some(Authentication::Anonymous) | None => {
+            Err(AppError::Unauthorized("authentication required".into()))
+        }
+    }
+}
+
+/// Field guard that admits callers whose token grants `role`, e.g.
+/// `#[graphql(guard = "RoleGuard::new(\"admin\")")]`.
+pub(crate) struct RoleGuard {
+    role: &'static str,
+}
+
+impl RoleGuard {
+    pub fn new(role: &'static str) -> Self {
+        RoleGuard { role }
+    }
+}
+
+#[async_trait]
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let claims = require_claims(ctx)?;
+        if claims.roles.iter().any(|role| role == self.role) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(format!("requires the '{}' role", self.role)).into())
+        }
+    }
+}
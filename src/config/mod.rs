@@ -0,0 +1,1251 @@
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::env;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_HOST: &str = "0.0.0.0";
+const DEFAULT_PORT: &str = "8000";
+const DEFAULT_MAX_DEPTH: usize = 15;
+const DEFAULT_MAX_COMPLEXITY: usize = 500;
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 10 * 1024 * 1024;
+const DEFAULT_MAX_UPLOAD_FILES: usize = 10;
+const DEFAULT_MAX_UPLOAD_REQUEST_SIZE: usize = 100 * 1024 * 1024;
+const DEFAULT_MAX_ALIASES: usize = 50;
+const DEFAULT_MAX_ROOT_FIELDS: usize = 20;
+const DEFAULT_APQ_CACHE_SIZE: usize = 1000;
+const DEFAULT_MAX_BATCH: usize = 10;
+const DEFAULT_MAX_REQUEST_BYTES: usize = 1024 * 1024;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_COMPRESSION_MIN_BYTES: u16 = 1024;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_UDS_MODE: u32 = 0o660;
+const DEFAULT_RATE_LIMIT_BURST: usize = 100;
+const DEFAULT_MAX_CONCURRENCY: usize = 512;
+const DEFAULT_WS_MAX_CONNECTIONS: usize = 1000;
+const DEFAULT_WS_MAX_SUBSCRIPTIONS: usize = 100;
+const DEFAULT_MAX_TITLE_LENGTH: usize = 200;
+const DEFAULT_MAX_BODY_LENGTH: usize = 10_000;
+const DEFAULT_LOADER_DELAY_MS: u64 = 1;
+const DEFAULT_LOADER_MAX_BATCH_SIZE: usize = 1000;
+const DEFAULT_METRICS_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const DEFAULT_LOG_DIRECTIVES: &str = "info,axum_graphql=debug";
+const DEFAULT_JAEGER_AGENT_HOST: &str = "localhost";
+const DEFAULT_JAEGER_AGENT_PORT: &str = "6831";
+const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4317";
+const DEFAULT_SERVICE_NAME: &str = "axum-graphql";
+
+/// Every setting the service reads, parsed once at startup.
+///
+/// Reading it stops at the first invalid value with a `ConfigError` naming
+/// the variable, so a misconfigured deployment fails before it binds.
+#[derive(Default)]
+pub(crate) struct Config {
+    pub server: ServerConfig,
+    pub graphql: GraphQLConfig,
+    pub cors: CorsConfig,
+    pub auth: AuthConfig,
+    pub metrics: MetricsConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let config = Config {
+            server: ServerConfig::from_env()?,
+            graphql: GraphQLConfig::from_env()?,
+            cors: CorsConfig::from_env()?,
+            auth: AuthConfig::from_env()?,
+            metrics: MetricsConfig::from_env()?,
+            telemetry: TelemetryConfig::from_env()?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects settings that are only fine on their own: unix socket peers
+    /// have no address for `METRICS_ALLOWED_CIDRS` to match.
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.server.uds.is_some() && !self.metrics.allowed_cidrs.is_empty() {
+            return Err(ConfigError::conflict("LISTEN_UDS", "METRICS_ALLOWED_CIDRS"));
+        }
+        Ok(())
+    }
+}
+
+/// Why the environment doesn't make a valid `Config`; the message names the
+/// variable at fault.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ConfigError(String);
+
+impl ConfigError {
+    fn invalid(key: &str, value: &str) -> Self {
+        ConfigError(format!("Invalid value for '{}': '{}'", key, value))
+    }
+
+    fn conflict(key: &str, other: &str) -> Self {
+        ConfigError(format!("'{}' and '{}' cannot be set together", key, other))
+    }
+
+    fn unpaired(key: &str, other: &str) -> Self {
+        ConfigError(format!("'{}' and '{}' must be set together", key, other))
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub(crate) struct ServerConfig {
+    pub host: IpAddr,
+    pub port: u16,
+    /// How `/health` shapes its body.
+    pub health_format: HealthFormat,
+    /// How long health reports shutting down, while requests are still
+    /// accepted, before the drain starts; gives load balancers time to notice.
+    pub lame_duck: Duration,
+    /// How long in-flight requests may run on once the drain starts.
+    pub shutdown_timeout: Duration,
+    /// Serves HTTPS when set, plain HTTP otherwise.
+    pub tls: Option<TlsConfig>,
+    /// Serves on a unix socket instead of `host` and `port` when set.
+    pub uds: Option<UdsConfig>,
+}
+
+/// Body layout of `/health`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum HealthFormat {
+    /// `{ "healthy": bool, "components": {...} }`.
+    Native,
+    /// `{ "status": "pass" | "fail", "checks": {...} }` as in the draft
+    /// health-check RFC, which Apollo Router and most load balancers read.
+    Rfc,
+}
+
+impl FromStr for HealthFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "native" => Ok(HealthFormat::Native),
+            "rfc" => Ok(HealthFormat::Rfc),
+            _ => Err(()),
+        }
+    }
+}
+
+/// PEM files for serving HTTPS.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// Unix domain socket to listen on, for a proxy on the same host.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct UdsConfig {
+    pub path: PathBuf,
+    /// Permission bits set on the socket file, `0o660` by default.
+    pub mode: u32,
+}
+
+impl ServerConfig {
+    /// Reads `BIND_ADDR` (e.g. `127.0.0.1:8080`) or, when unset, `HOST` and
+    /// `PORT` (`0` for any free port), `LAME_DUCK_SECONDS`,
+    /// `SHUTDOWN_TIMEOUT_SECS` (or `SHUTDOWN_GRACE_SECONDS`), `TLS_CERT_PATH`
+    /// with `TLS_KEY_PATH`, and `HEALTH_FORMAT` (`native`, the default, or
+    /// `rfc`). `LISTEN_UDS` with `LISTEN_UDS_MODE` replaces the TCP settings;
+    /// `HOST` is then ignored rather than refused, since shells and container
+    /// runtimes often set it.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let health_format = parse_env("HEALTH_FORMAT", HealthFormat::Native)?;
+        let lame_duck = Duration::from_secs(parse_env("LAME_DUCK_SECONDS", 0)?);
+        let shutdown_timeout = Duration::from_secs(parse_env(
+            env_key("SHUTDOWN_TIMEOUT_SECS", "SHUTDOWN_GRACE_SECONDS")?,
+            DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+        )?);
+        let tls = tls_config(
+            env::var_os("TLS_CERT_PATH").map(PathBuf::from),
+            env::var_os("TLS_KEY_PATH").map(PathBuf::from),
+        )?;
+        let uds = uds_config(
+            env::var_os("LISTEN_UDS").map(PathBuf::from),
+            env::var("LISTEN_UDS_MODE").ok(),
+            ["BIND_ADDR", "PORT", "TLS_CERT_PATH"]
+                .into_iter()
+                .find(|key| env::var_os(key).is_some()),
+        )?;
+        if uds.is_some() {
+            return Ok(ServerConfig {
+                health_format,
+                lame_duck,
+                shutdown_timeout,
+                tls,
+                uds,
+                ..ServerConfig::default()
+            });
+        }
+        if let Ok(bind_addr) = env::var("BIND_ADDR") {
+            let addr: SocketAddr = bind_addr.parse().map_err(|_| {
+                ConfigError(format!(
+                    "Invalid value for 'BIND_ADDR': '{}' (expected <ip>:<port>)",
+                    bind_addr
+                ))
+            })?;
+            return Ok(ServerConfig {
+                host: addr.ip(),
+                port: addr.port(),
+                health_format,
+                lame_duck,
+                shutdown_timeout,
+                tls,
+                uds,
+            });
+        }
+
+        let host = env::var("HOST").unwrap_or_else(|_| DEFAULT_HOST.into());
+        let port = env::var("PORT").unwrap_or_else(|_| DEFAULT_PORT.into());
+
+        Ok(ServerConfig {
+            host: host.parse().map_err(|_| {
+                ConfigError(format!(
+                    "Invalid value for 'HOST': '{}' (expected an IP address)",
+                    host
+                ))
+            })?,
+            port: port.parse().map_err(|_| {
+                ConfigError(format!(
+                    "Invalid value for 'PORT': '{}' (expected a number between 0 and 65535)",
+                    port
+                ))
+            })?,
+            health_format,
+            lame_duck,
+            shutdown_timeout,
+            tls,
+            uds,
+        })
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: DEFAULT_HOST.parse().unwrap(),
+            port: DEFAULT_PORT.parse().unwrap(),
+            health_format: HealthFormat::Native,
+            lame_duck: Duration::ZERO,
+            shutdown_timeout: Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+            tls: None,
+            uds: None,
+        }
+    }
+}
+
+/// The in-browser IDE served on `GET /`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GraphQLIde {
+    Playground,
+    GraphiQL,
+    None,
+}
+
+impl FromStr for GraphQLIde {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "playground" => Ok(GraphQLIde::Playground),
+            "graphiql" => Ok(GraphQLIde::GraphiQL),
+            "none" => Ok(GraphQLIde::None),
+            _ => Err(()),
+        }
+    }
+}
+
+/// User name and password for the IDE page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct IdeCredentials {
+    pub user: String,
+    pub password: String,
+}
+
+pub(crate) struct GraphQLConfig {
+    /// `None` disables the depth limit.
+    pub max_depth: Option<usize>,
+    /// `None` disables the complexity limit.
+    pub max_complexity: Option<usize>,
+    /// Most aliases in one operation; `None` disables the limit.
+    pub max_aliases: Option<usize>,
+    /// Most root fields in one operation; `None` disables the limit.
+    pub max_root_fields: Option<usize>,
+    /// Hides `__schema`/`__type` and stops serving the IDE.
+    pub disable_introspection: bool,
+    /// Which IDE `GET /` serves while introspection is enabled.
+    pub ide: GraphQLIde,
+    /// Basic auth the IDE page asks for; `None` serves it to anyone.
+    pub ide_credentials: Option<IdeCredentials>,
+    /// Path prefix a reverse proxy mounts the service under, such as `/api`;
+    /// empty when served from the root. The IDE page points its endpoints here.
+    pub base_path: String,
+    /// Builds the schema as an Apollo Federation subgraph.
+    pub federation: bool,
+    /// How long the schema-wide data loaders wait to collect a batch.
+    pub loader_delay: Duration,
+    pub loader_max_batch_size: usize,
+    /// Largest accepted file in a multipart request, in bytes.
+    pub max_upload_size: usize,
+    /// Most files accepted in a multipart request; `None` is unlimited.
+    pub max_upload_files: Option<usize>,
+    /// Largest accepted multipart request, in bytes, across all its files.
+    pub max_upload_request_size: usize,
+    /// Capacity of the persisted query cache; `None` disables APQ.
+    pub apq_cache_size: Option<usize>,
+    /// Most operations accepted in one batched request; `None` is unlimited.
+    pub max_batch_size: Option<usize>,
+    /// Largest accepted JSON request body, in bytes; read from
+    /// `MAX_REQUEST_BYTES` or `GRAPHQL_MAX_BODY_BYTES`.
+    pub max_request_bytes: usize,
+    /// How long a request may take: GraphQL requests are then answered with
+    /// a `TIMEOUT` error, everything else with `408`. Read from
+    /// `REQUEST_TIMEOUT_SECS` or `REQUEST_TIMEOUT_SECONDS`.
+    pub request_timeout: Duration,
+    /// Smallest response body, in bytes, worth compressing.
+    pub compression_min_bytes: u16,
+    /// Requests per second allowed per client; `None`, the default, disables
+    /// throttling. Read from `RATE_LIMIT_RPS` or `RATE_LIMIT_PER_SECOND`.
+    pub rate_limit_rps: Option<usize>,
+    /// Requests a client IP may send at once before being held to the rate.
+    pub rate_limit_burst: usize,
+    /// Proxies whose `X-Forwarded-For` names the client to rate limit.
+    pub rate_limit_trusted_proxies: Vec<Cidr>,
+    /// Header carrying an API key to rate limit by instead of the client IP.
+    pub rate_limit_key_header: Option<HeaderName>,
+    /// Keys `rate_limit_key_header` may carry; any other value is ignored
+    /// and the request is counted against its IP.
+    pub rate_limit_api_keys: Vec<String>,
+    /// Most GraphQL requests executed at once; `None` allows any number.
+    /// Read from `MAX_CONCURRENCY` or `MAX_CONCURRENT_REQUESTS`.
+    pub max_concurrency: Option<usize>,
+    /// Most WebSocket connections open at once; `None` allows any number.
+    pub ws_max_connections: Option<usize>,
+    /// Most operations one WebSocket connection may run at once.
+    pub ws_max_subscriptions: Option<usize>,
+    /// Replaces unexpected resolver errors with a generic message; read from
+    /// `GRAPHQL_MASK_ERRORS` or `MASK_ERRORS`.
+    pub mask_errors: bool,
+    /// Adds per-resolver timings to `extensions.tracing` in every response.
+    pub apollo_tracing: bool,
+    /// Emits a tracing span per parse/validation step and resolved field.
+    pub field_tracing: bool,
+    /// JSON map of `{ hash: document }`; when set only those operations run.
+    pub allowlist_file: Option<PathBuf>,
+    /// Lets introspection queries bypass the allow-list.
+    pub allowlist_introspection: bool,
+    /// Longest accepted note title, in characters.
+    pub max_title_length: usize,
+    /// Longest accepted note body, in characters.
+    pub max_body_length: usize,
+    /// Reuses responses to identical anonymous queries for their `max_age`.
+    pub response_cache: bool,
+    /// Longest a cached response is reused, whatever its `max_age`; `None`
+    /// leaves it to `max_age`.
+    pub response_cache_ttl: Option<Duration>,
+}
+
+impl GraphQLConfig {
+    /// Reads `GRAPHQL_MAX_DEPTH`, `GRAPHQL_MAX_COMPLEXITY`, `GRAPHQL_MAX_ALIASES`
+    /// and `GRAPHQL_MAX_ROOT_FIELDS`; `0` disables a limit.
+    ///
+    /// Introspection follows `GRAPHQL_INTROSPECTION`; when unset it is enabled
+    /// in debug builds and disabled in release builds or with
+    /// `ENVIRONMENT=production`; the older `GRAPHQL_DISABLE_INTROSPECTION`
+    /// is still read as its inverse. `GRAPHQL_IDE` picks `playground` (the
+    /// default), `graphiql` or `none`; `PLAYGROUND_USER` with
+    /// `PLAYGROUND_PASSWORD` puts it behind basic auth, and `BASE_PATH`
+    /// prefixes the endpoints it talks to.
+    ///
+    /// `RATE_LIMIT_KEY_HEADER` is set together with the comma-separated
+    /// `RATE_LIMIT_API_KEYS` it is checked against.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let production = env::var("ENVIRONMENT").map_or(false, |env| env == "production");
+        let introspection_key = env_key("GRAPHQL_INTROSPECTION", "GRAPHQL_DISABLE_INTROSPECTION")?;
+        let introspection = match introspection_key {
+            "GRAPHQL_DISABLE_INTROSPECTION" => !parse_env("GRAPHQL_DISABLE_INTROSPECTION", false)?,
+            key => parse_env(key, cfg!(debug_assertions) && !production)?,
+        };
+        let rate_limit_key_header = env::var("RATE_LIMIT_KEY_HEADER")
+            .ok()
+            .map(|name| {
+                name.parse()
+                    .map_err(|_| ConfigError::invalid("RATE_LIMIT_KEY_HEADER", &name))
+            })
+            .transpose()?;
+        let rate_limit_api_keys = env::var("RATE_LIMIT_API_KEYS")
+            .map_or(Ok(Vec::new()), |keys| {
+                parse_list("RATE_LIMIT_API_KEYS", &keys)
+            })?;
+        if rate_limit_key_header.is_some() == rate_limit_api_keys.is_empty() {
+            return Err(ConfigError::unpaired(
+                "RATE_LIMIT_KEY_HEADER",
+                "RATE_LIMIT_API_KEYS",
+            ));
+        }
+        Ok(GraphQLConfig {
+            max_depth: limit_from_env("GRAPHQL_MAX_DEPTH", DEFAULT_MAX_DEPTH)?,
+            max_complexity: limit_from_env("GRAPHQL_MAX_COMPLEXITY", DEFAULT_MAX_COMPLEXITY)?,
+            max_aliases: limit_from_env("GRAPHQL_MAX_ALIASES", DEFAULT_MAX_ALIASES)?,
+            max_root_fields: limit_from_env("GRAPHQL_MAX_ROOT_FIELDS", DEFAULT_MAX_ROOT_FIELDS)?,
+            disable_introspection: !introspection,
+            ide: parse_env("GRAPHQL_IDE", GraphQLIde::Playground)?,
+            ide_credentials: ide_credentials(
+                env::var("PLAYGROUND_USER").ok(),
+                env::var("PLAYGROUND_PASSWORD").ok(),
+            )?,
+            base_path: base_path(&env::var("BASE_PATH").unwrap_or_default()),
+            federation: parse_env("GRAPHQL_FEDERATION", false)?,
+            loader_delay: Duration::from_millis(parse_env(
+                "DATALOADER_DELAY_MS",
+                DEFAULT_LOADER_DELAY_MS,
+            )?),
+            loader_max_batch_size: parse_env(
+                "DATALOADER_MAX_BATCH_SIZE",
+                DEFAULT_LOADER_MAX_BATCH_SIZE,
+            )?,
+            max_upload_size: parse_env("GRAPHQL_MAX_UPLOAD_SIZE", DEFAULT_MAX_UPLOAD_SIZE)?,
+            max_upload_files: limit_from_env("GRAPHQL_MAX_UPLOAD_FILES", DEFAULT_MAX_UPLOAD_FILES)?,
+            max_upload_request_size: parse_env(
+                "GRAPHQL_MAX_UPLOAD_REQUEST_SIZE",
+                DEFAULT_MAX_UPLOAD_REQUEST_SIZE,
+            )?,
+            apq_cache_size: limit_from_env("APQ_CACHE_SIZE", DEFAULT_APQ_CACHE_SIZE)?,
+            max_batch_size: limit_from_env("GRAPHQL_MAX_BATCH", DEFAULT_MAX_BATCH)?,
+            max_request_bytes: parse_env(
+                env_key("MAX_REQUEST_BYTES", "GRAPHQL_MAX_BODY_BYTES")?,
+                DEFAULT_MAX_REQUEST_BYTES,
+            )?,
+            request_timeout: Duration::from_secs(parse_env(
+                env_key("REQUEST_TIMEOUT_SECS", "REQUEST_TIMEOUT_SECONDS")?,
+                DEFAULT_REQUEST_TIMEOUT_SECS,
+            )?),
+            compression_min_bytes: parse_env(
+                "COMPRESSION_MIN_BYTES",
+                DEFAULT_COMPRESSION_MIN_BYTES,
+            )?,
+            rate_limit_rps: limit_from_env(env_key("RATE_LIMIT_RPS", "RATE_LIMIT_PER_SECOND")?, 0)?,
+            rate_limit_burst: parse_env("RATE_LIMIT_BURST", DEFAULT_RATE_LIMIT_BURST)?,
+            rate_limit_trusted_proxies: env::var("RATE_LIMIT_TRUSTED_PROXIES")
+                .map_or(Ok(Vec::new()), |cidrs| {
+                    parse_list("RATE_LIMIT_TRUSTED_PROXIES", &cidrs)
+                })?,
+            rate_limit_key_header,
+            rate_limit_api_keys,
+            max_concurrency: limit_from_env(
+                env_key("MAX_CONCURRENCY", "MAX_CONCURRENT_REQUESTS")?,
+                DEFAULT_MAX_CONCURRENCY,
+            )?,
+            ws_max_connections: limit_from_env("WS_MAX_CONNECTIONS", DEFAULT_WS_MAX_CONNECTIONS)?,
+            ws_max_subscriptions: limit_from_env(
+                "WS_MAX_SUBSCRIPTIONS",
+                DEFAULT_WS_MAX_SUBSCRIPTIONS,
+            )?,
+            mask_errors: parse_env(env_key("GRAPHQL_MASK_ERRORS", "MASK_ERRORS")?, true)?,
+            apollo_tracing: parse_env("GRAPHQL_APOLLO_TRACING", false)?,
+            field_tracing: parse_env("GRAPHQL_FIELD_TRACING", false)?,
+            allowlist_file: env::var_os("GRAPHQL_ALLOWLIST_FILE").map(PathBuf::from),
+            allowlist_introspection: parse_env("GRAPHQL_ALLOWLIST_INTROSPECTION", false)?,
+            max_title_length: parse_env("GRAPHQL_MAX_TITLE_LENGTH", DEFAULT_MAX_TITLE_LENGTH)?,
+            max_body_length: parse_env("GRAPHQL_MAX_BODY_LENGTH", DEFAULT_MAX_BODY_LENGTH)?,
+            response_cache: env::var("GRAPHQL_RESPONSE_CACHE").map_or(true, |value| value != "off"),
+            response_cache_ttl: limit_from_env("QUERY_CACHE_TTL_SECS", 0)?
+                .map(|secs| Duration::from_secs(secs as u64)),
+        })
+    }
+}
+
+impl Default for GraphQLConfig {
+    fn default() -> Self {
+        GraphQLConfig {
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_complexity: Some(DEFAULT_MAX_COMPLEXITY),
+            max_aliases: Some(DEFAULT_MAX_ALIASES),
+            max_root_fields: Some(DEFAULT_MAX_ROOT_FIELDS),
+            disable_introspection: false,
+            ide: GraphQLIde::Playground,
+            ide_credentials: None,
+            base_path: String::new(),
+            federation: false,
+            loader_delay: Duration::from_millis(DEFAULT_LOADER_DELAY_MS),
+            loader_max_batch_size: DEFAULT_LOADER_MAX_BATCH_SIZE,
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+            max_upload_files: Some(DEFAULT_MAX_UPLOAD_FILES),
+            max_upload_request_size: DEFAULT_MAX_UPLOAD_REQUEST_SIZE,
+            apq_cache_size: Some(DEFAULT_APQ_CACHE_SIZE),
+            max_batch_size: Some(DEFAULT_MAX_BATCH),
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+            request_timeout: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            compression_min_bytes: DEFAULT_COMPRESSION_MIN_BYTES,
+            rate_limit_rps: None,
+            rate_limit_burst: DEFAULT_RATE_LIMIT_BURST,
+            rate_limit_trusted_proxies: Vec::new(),
+            rate_limit_key_header: None,
+            rate_limit_api_keys: Vec::new(),
+            max_concurrency: Some(DEFAULT_MAX_CONCURRENCY),
+            ws_max_connections: Some(DEFAULT_WS_MAX_CONNECTIONS),
+            ws_max_subscriptions: Some(DEFAULT_WS_MAX_SUBSCRIPTIONS),
+            mask_errors: true,
+            apollo_tracing: false,
+            field_tracing: false,
+            allowlist_file: None,
+            allowlist_introspection: false,
+            max_title_length: DEFAULT_MAX_TITLE_LENGTH,
+            max_body_length: DEFAULT_MAX_BODY_LENGTH,
+            response_cache: true,
+            response_cache_ttl: None,
+        }
+    }
+}
+
+pub(crate) struct CorsConfig {
+    /// `None` allows any origin.
+    pub allowed_origins: Option<Vec<HeaderValue>>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+    /// Lets browsers send cookies and authorization headers cross-origin;
+    /// requires an explicit origin list.
+    pub allow_credentials: bool,
+}
+
+impl CorsConfig {
+    /// Reads the comma-separated `CORS_ALLOWED_ORIGINS`, `CORS_ALLOWED_METHODS`
+    /// and `CORS_ALLOWED_HEADERS`.
+    ///
+    /// Origins default to any origin in debug builds and to none in release
+    /// builds; `*` allows any origin explicitly. `CORS_ALLOW_CREDENTIALS`
+    /// can't be combined with any origin.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let allowed_origins = match env::var("CORS_ALLOWED_ORIGINS") {
+            Ok(origins) if origins.trim() == "*" => None,
+            Ok(origins) => Some(parse_list("CORS_ALLOWED_ORIGINS", &origins)?),
+            Err(_) if cfg!(debug_assertions) => None,
+            Err(_) => Some(Vec::new()),
+        };
+        let methods =
+            env::var("CORS_ALLOWED_METHODS").unwrap_or_else(|_| "GET,POST,OPTIONS".into());
+        let headers = env::var("CORS_ALLOWED_HEADERS")
+            .unwrap_or_else(|_| "content-type,authorization".into());
+
+        let config = CorsConfig {
+            allowed_origins,
+            allowed_methods: parse_list("CORS_ALLOWED_METHODS", &methods)?,
+            allowed_headers: parse_list("CORS_ALLOWED_HEADERS", &headers)?,
+            allow_credentials: parse_env("CORS_ALLOW_CREDENTIALS", false)?,
+        };
+        config.validate().map_err(|err| {
+            ConfigError(format!(
+                "Invalid value for 'CORS_ALLOW_CREDENTIALS': {}",
+                err
+            ))
+        })?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.allow_credentials && self.allowed_origins.is_none() {
+            return Err("credentials can't be allowed for any origin; list CORS_ALLOWED_ORIGINS");
+        }
+        Ok(())
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: None,
+            allowed_methods: vec![Method::GET, Method::POST, Method::OPTIONS],
+            allowed_headers: vec![header::CONTENT_TYPE, header::AUTHORIZATION],
+            allow_credentials: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct AuthConfig {
+    /// HS256 secret for bearer tokens; without it every token is rejected.
+    pub jwt_secret: Option<String>,
+    /// Refuses GraphQL requests without a valid token instead of running them
+    /// anonymously.
+    pub required: bool,
+}
+
+impl AuthConfig {
+    /// Reads `JWT_SECRET` and `AUTH_REQUIRED`, which needs a secret.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let jwt_secret = env::var("JWT_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty());
+        let required = parse_env("AUTH_REQUIRED", false)?;
+        if required && jwt_secret.is_none() {
+            return Err(ConfigError(
+                "'AUTH_REQUIRED' needs 'JWT_SECRET' to be set".into(),
+            ));
+        }
+        Ok(AuthConfig {
+            jwt_secret,
+            required,
+        })
+    }
+}
+
+pub(crate) struct MetricsConfig {
+    /// Upper bounds in seconds of the request latency histogram buckets.
+    pub buckets: Vec<f64>,
+    /// Token `/metrics` requires; `None` leaves it open.
+    pub auth_token: Option<String>,
+    /// Peer networks `/metrics` answers; empty answers every peer.
+    pub allowed_cidrs: Vec<Cidr>,
+}
+
+/// An IP network such as `10.0.0.0/8`; a bare address is a single host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V4(ip)) => prefix_matches(
+                &network.octets(),
+                &ip.to_ipv6_mapped().octets(),
+                self.prefix,
+            ),
+            (IpAddr::V4(_), IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+                Some(ip) => self.contains(IpAddr::V4(ip)),
+                None => false,
+            },
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (network, prefix) = match value.split_once('/') {
+            Some((network, prefix)) => (network, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = network.parse().map_err(|_| ())?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| ())?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(());
+        }
+        Ok(Cidr { network, prefix })
+    }
+}
+
+fn prefix_matches(network: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let (bytes, bits) = ((prefix / 8) as usize, prefix % 8);
+    if network[..bytes] != ip[..bytes] {
+        return false;
+    }
+    bits == 0 || (network[bytes] ^ ip[bytes]) >> (8 - bits) == 0
+}
+
+impl MetricsConfig {
+    /// Reads the comma-separated `METRICS_BUCKETS`, which must be strictly
+    /// increasing, `METRICS_AUTH_TOKEN` and the comma-separated
+    /// `METRICS_ALLOWED_CIDRS`.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let buckets = match env::var("METRICS_BUCKETS") {
+            Ok(buckets) => parse_list("METRICS_BUCKETS", &buckets)?,
+            Err(_) => DEFAULT_METRICS_BUCKETS.to_vec(),
+        };
+        validate_buckets(&buckets)
+            .map_err(|err| ConfigError(format!("Invalid value for 'METRICS_BUCKETS': {}", err)))?;
+
+        Ok(MetricsConfig {
+            buckets,
+            auth_token: env::var("METRICS_AUTH_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            allowed_cidrs: env::var("METRICS_ALLOWED_CIDRS").map_or(Ok(Vec::new()), |cidrs| {
+                parse_list("METRICS_ALLOWED_CIDRS", &cidrs)
+            })?,
+        })
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            buckets: DEFAULT_METRICS_BUCKETS.to_vec(),
+            auth_token: None,
+            allowed_cidrs: Vec::new(),
+        }
+    }
+}
+
+pub(crate) struct TelemetryConfig {
+    pub log_format: LogFormat,
+    /// Level filter in `RUST_LOG` syntax, already checked to parse.
+    pub log_directives: String,
+    /// Where spans are sent; `None` doesn't export them.
+    pub exporter: Option<TraceExporter>,
+    /// Share of new traces that are sampled; `None` samples every trace.
+    pub sample_ratio: Option<f64>,
+    /// `service.name` of the exported spans.
+    pub service_name: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            LogFormat::Pretty
+        } else {
+            LogFormat::Json
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum TraceExporter {
+    /// A Jaeger agent at `host:port`.
+    Jaeger { agent_endpoint: String },
+    /// An OTLP collector reached over gRPC.
+    Otlp { endpoint: String },
+}
+
+impl TelemetryConfig {
+    /// Reads `LOG_FORMAT` (`pretty`, the default in debug builds, or `json`)
+    /// and `RUST_LOG`, which defaults to `info,axum_graphql=debug`.
+    ///
+    /// Spans go to the exporter named by `OTEL_EXPORTER` (`jaeger` or
+    /// `otlp`); when it is unset, Jaeger is used if `JAEGER_ENABLED` is true.
+    /// Jaeger reads `JAEGER_AGENT_HOST` and `JAEGER_AGENT_PORT`, OTLP reads
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT`, and both take the service name from
+    /// `TRACING_SERVICE_NAME`. `OTEL_TRACES_SAMPLER_ARG` sets the ratio.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let exporter = trace_exporter(
+            env::var("OTEL_EXPORTER").ok().as_deref(),
+            parse_env("JAEGER_ENABLED", false)?,
+        )?;
+        Ok(TelemetryConfig {
+            log_format: parse_env("LOG_FORMAT", LogFormat::default())?,
+            log_directives: log_directives(env::var("RUST_LOG").ok())?,
+            exporter,
+            sample_ratio: env::var("OTEL_TRACES_SAMPLER_ARG")
+                .ok()
+                .map(|arg| sample_ratio(&arg))
+                .transpose()?,
+            service_name: env::var("TRACING_SERVICE_NAME")
+                .unwrap_or_else(|_| DEFAULT_SERVICE_NAME.into()),
+        })
+    }
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            log_format: LogFormat::default(),
+            log_directives: DEFAULT_LOG_DIRECTIVES.into(),
+            exporter: None,
+            sample_ratio: None,
+            service_name: DEFAULT_SERVICE_NAME.into(),
+        }
+    }
+}
+
+fn tls_config(
+    cert_path: Option<PathBuf>,
+    key_path: Option<PathBuf>,
+) -> Result<Option<TlsConfig>, ConfigError> {
+    match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => Ok(Some(TlsConfig {
+            cert_path,
+            key_path,
+        })),
+        (None, None) => Ok(None),
+        _ => Err(ConfigError::unpaired("TLS_CERT_PATH", "TLS_KEY_PATH")),
+    }
+}
+
+/// `conflicting` is the first TCP or TLS variable that is set, which can't be
+/// combined with a unix socket.
+fn uds_config(
+    path: Option<PathBuf>,
+    mode: Option<String>,
+    conflicting: Option<&str>,
+) -> Result<Option<UdsConfig>, ConfigError> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    if let Some(key) = conflicting {
+        return Err(ConfigError::conflict("LISTEN_UDS", key));
+    }
+    let mode = match mode {
+        Some(mode) => u32::from_str_radix(&mode, 8)
+            .ok()
+            .filter(|bits| *bits <= 0o777)
+            .ok_or_else(|| {
+                ConfigError(format!(
+                    "Invalid value for 'LISTEN_UDS_MODE': '{}' (expected octal permissions such as 660)",
+                    mode
+                ))
+            })?,
+        None => DEFAULT_UDS_MODE,
+    };
+    Ok(Some(UdsConfig { path, mode }))
+}
+
+/// Normalizes `BASE_PATH` to a leading slash and no trailing one.
+fn base_path(value: &str) -> String {
+    let trimmed = value.trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+fn ide_credentials(
+    user: Option<String>,
+    password: Option<String>,
+) -> Result<Option<IdeCredentials>, ConfigError> {
+    match (user, password) {
+        (Some(user), Some(password)) => Ok(Some(IdeCredentials { user, password })),
+        (None, None) => Ok(None),
+        _ => Err(ConfigError::unpaired(
+            "PLAYGROUND_USER",
+            "PLAYGROUND_PASSWORD",
+        )),
+    }
+}
+
+fn log_directives(directives: Option<String>) -> Result<String, ConfigError> {
+    let directives = directives.unwrap_or_else(|| DEFAULT_LOG_DIRECTIVES.into());
+    match EnvFilter::try_new(&directives) {
+        Ok(_) => Ok(directives),
+        Err(err) => Err(ConfigError(format!(
+            "Invalid value for 'RUST_LOG': '{}': {}",
+            directives, err
+        ))),
+    }
+}
+
+fn trace_exporter(
+    exporter: Option<&str>,
+    jaeger_enabled: bool,
+) -> Result<Option<TraceExporter>, ConfigError> {
+    let jaeger = || TraceExporter::Jaeger {
+        agent_endpoint: format!(
+            "{}:{}",
+            env::var("JAEGER_AGENT_HOST").unwrap_or_else(|_| DEFAULT_JAEGER_AGENT_HOST.into()),
+            env::var("JAEGER_AGENT_PORT").unwrap_or_else(|_| DEFAULT_JAEGER_AGENT_PORT.into()),
+        ),
+    };
+    match exporter {
+        Some("jaeger") => Ok(Some(jaeger())),
+        Some("otlp") => Ok(Some(TraceExporter::Otlp {
+            endpoint: env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .unwrap_or_else(|_| DEFAULT_OTLP_ENDPOINT.into()),
+        })),
+        Some(other) => Err(ConfigError::invalid("OTEL_EXPORTER", other)),
+        None if jaeger_enabled => Ok(Some(jaeger())),
+        None => Ok(None),
+    }
+}
+
+fn sample_ratio(arg: &str) -> Result<f64, ConfigError> {
+    arg.parse::<f64>()
+        .ok()
+        .filter(|ratio| (0.0..=1.0).contains(ratio))
+        .ok_or_else(|| ConfigError::invalid("OTEL_TRACES_SAMPLER_ARG", arg))
+}
+
+fn validate_buckets(buckets: &[f64]) -> Result<(), &'static str> {
+    if buckets.is_empty() {
+        return Err("at least one bucket is required");
+    }
+    if buckets.iter().any(|bucket| !bucket.is_finite()) {
+        return Err("buckets must be finite numbers");
+    }
+    if buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("buckets must be strictly increasing");
+    }
+    Ok(())
+}
+
+fn parse_list<T: FromStr>(key: &str, value: &str) -> Result<Vec<T>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().map_err(|_| ConfigError::invalid(key, item)))
+        .collect()
+}
+
+fn parse_env<T: FromStr>(key: &str, default: T) -> Result<T, ConfigError> {
+    match env::var(key) {
+        Ok(value) => value.parse().map_err(|_| ConfigError::invalid(key, &value)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// The variable a setting is read from: `alias`, another name it is known
+/// by, when only that one is set.
+fn env_key(key: &'static str, alias: &'static str) -> Result<&'static str, ConfigError> {
+    match (env::var_os(key), env::var_os(alias)) {
+        (Some(_), Some(_)) => Err(ConfigError::conflict(key, alias)),
+        (None, Some(_)) => Ok(alias),
+        _ => Ok(key),
+    }
+}
+
+fn limit_from_env(key: &str, default: usize) -> Result<Option<usize>, ConfigError> {
+    match parse_env(key, default)? {
+        0 => Ok(None),
+        limit => Ok(Some(limit)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limit_defaults_when_unset() {
+        env::remove_var("TEST_LIMIT_UNSET");
+        assert_eq!(limit_from_env("TEST_LIMIT_UNSET", 500).unwrap(), Some(500));
+    }
+
+    #[test]
+    fn limit_zero_is_unlimited() {
+        env::set_var("TEST_LIMIT_ZERO", "0");
+        assert_eq!(limit_from_env("TEST_LIMIT_ZERO", 500).unwrap(), None);
+    }
+
+    #[test]
+    fn limit_is_read_from_env() {
+        env::set_var("TEST_LIMIT_SET", "25");
+        assert_eq!(limit_from_env("TEST_LIMIT_SET", 500).unwrap(), Some(25));
+    }
+
+    #[test]
+    fn invalid_limit_names_the_variable() {
+        env::set_var("TEST_LIMIT_INVALID", "many");
+        assert_eq!(
+            limit_from_env("TEST_LIMIT_INVALID", 500)
+                .unwrap_err()
+                .to_string(),
+            "Invalid value for 'TEST_LIMIT_INVALID': 'many'"
+        );
+    }
+
+    #[test]
+    fn alias_is_read_when_the_name_is_unset() {
+        assert_eq!(
+            env_key("TEST_ALIAS_NAME", "TEST_ALIAS_OTHER"),
+            Ok("TEST_ALIAS_NAME")
+        );
+        env::set_var("TEST_ALIAS_OTHER", "1");
+        assert_eq!(
+            env_key("TEST_ALIAS_NAME", "TEST_ALIAS_OTHER"),
+            Ok("TEST_ALIAS_OTHER")
+        );
+    }
+
+    #[test]
+    fn alias_and_name_exclude_each_other() {
+        env::set_var("TEST_ALIAS_BOTH", "1");
+        env::set_var("TEST_ALIAS_BOTH_OLD", "2");
+        assert_eq!(
+            env_key("TEST_ALIAS_BOTH", "TEST_ALIAS_BOTH_OLD")
+                .unwrap_err()
+                .to_string(),
+            "'TEST_ALIAS_BOTH' and 'TEST_ALIAS_BOTH_OLD' cannot be set together"
+        );
+    }
+
+    #[test]
+    fn server_defaults_to_all_interfaces() {
+        let server = ServerConfig::default();
+        assert_eq!(
+            server.socket_addr(),
+            "0.0.0.0:8000".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(server.shutdown_timeout, Duration::from_secs(30));
+        assert_eq!(server.tls, None);
+    }
+
+    #[test]
+    fn invalid_duration_names_the_variable() {
+        env::set_var("TEST_TIMEOUT_INVALID", "soon");
+        assert_eq!(
+            parse_env::<u64>("TEST_TIMEOUT_INVALID", 30)
+                .unwrap_err()
+                .to_string(),
+            "Invalid value for 'TEST_TIMEOUT_INVALID': 'soon'"
+        );
+    }
+
+    #[test]
+    fn tls_is_enabled_by_both_paths() {
+        assert_eq!(tls_config(None, None), Ok(None));
+        assert_eq!(
+            tls_config(Some("cert.pem".into()), Some("key.pem".into())),
+            Ok(Some(TlsConfig {
+                cert_path: "cert.pem".into(),
+                key_path: "key.pem".into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn tls_needs_a_key_with_the_certificate() {
+        assert_eq!(
+            tls_config(Some("cert.pem".into()), None)
+                .unwrap_err()
+                .to_string(),
+            "'TLS_CERT_PATH' and 'TLS_KEY_PATH' must be set together"
+        );
+    }
+
+    #[test]
+    fn base_path_is_normalized() {
+        assert_eq!(base_path(""), "");
+        assert_eq!(base_path("/"), "");
+        assert_eq!(base_path("api"), "/api");
+        assert_eq!(base_path("/api/v1/"), "/api/v1");
+    }
+
+    #[test]
+    fn uds_mode_is_read_as_octal() {
+        let uds = uds_config(Some("/tmp/a.sock".into()), Some("600".into()), None);
+        assert_eq!(uds.unwrap().unwrap().mode, 0o600);
+        let uds = uds_config(Some("/tmp/a.sock".into()), None, None);
+        assert_eq!(uds.unwrap().unwrap().mode, 0o660);
+        assert_eq!(uds_config(None, Some("600".into()), Some("PORT")), Ok(None));
+    }
+
+    #[test]
+    fn uds_excludes_tcp() {
+        assert_eq!(
+            uds_config(Some("/tmp/a.sock".into()), None, Some("PORT"))
+                .unwrap_err()
+                .to_string(),
+            "'LISTEN_UDS' and 'PORT' cannot be set together"
+        );
+    }
+
+    #[test]
+    fn invalid_uds_mode_is_rejected() {
+        let err = uds_config(Some("/tmp/a.sock".into()), Some("999".into()), None).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid value for 'LISTEN_UDS_MODE': '999'"));
+    }
+
+    #[test]
+    fn uds_excludes_the_metrics_allow_list() {
+        let config = Config {
+            server: ServerConfig {
+                uds: uds_config(Some("/tmp/a.sock".into()), None, None).unwrap(),
+                ..ServerConfig::default()
+            },
+            metrics: MetricsConfig {
+                allowed_cidrs: vec!["10.0.0.0/8".parse().unwrap()],
+                ..MetricsConfig::default()
+            },
+            ..Config::default()
+        };
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "'LISTEN_UDS' and 'METRICS_ALLOWED_CIDRS' cannot be set together"
+        );
+    }
+
+    #[test]
+    fn ide_credentials_need_a_password() {
+        assert_eq!(
+            ide_credentials(Some("staging".into()), None)
+                .unwrap_err()
+                .to_string(),
+            "'PLAYGROUND_USER' and 'PLAYGROUND_PASSWORD' must be set together"
+        );
+    }
+
+    #[test]
+    fn cors_credentials_need_listed_origins() {
+        let wildcard = CorsConfig {
+            allow_credentials: true,
+            ..CorsConfig::default()
+        };
+        assert!(wildcard.validate().is_err());
+
+        let listed = CorsConfig {
+            allowed_origins: Some(vec![HeaderValue::from_static("https://app.example.com")]),
+            ..wildcard
+        };
+        assert_eq!(listed.validate(), Ok(()));
+    }
+
+    #[test]
+    fn cidrs_match_their_prefix() {
+        let private: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(private.contains("10.1.2.3".parse().unwrap()));
+        assert!(private.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!private.contains("11.0.0.1".parse().unwrap()));
+
+        let narrow: Cidr = "192.168.1.128/25".parse().unwrap();
+        assert!(narrow.contains("192.168.1.200".parse().unwrap()));
+        assert!(!narrow.contains("192.168.1.127".parse().unwrap()));
+
+        let host: Cidr = "::1".parse().unwrap();
+        assert!(host.contains("::1".parse().unwrap()));
+        assert!(!host.contains("127.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn malformed_cidrs_are_rejected() {
+        for cidr in ["10.0.0.0/33", "10.0.0/8", "fe80::/129", "10.0.0.0/"] {
+            assert_eq!(cidr.parse::<Cidr>(), Err(()), "{}", cidr);
+        }
+    }
+
+    #[test]
+    fn buckets_are_read_as_a_list() {
+        let buckets: Vec<f64> = parse_list("TEST_BUCKETS", "0.1, 0.5,2").unwrap();
+        assert_eq!(buckets, vec![0.1, 0.5, 2.0]);
+        assert_eq!(validate_buckets(&buckets), Ok(()));
+        assert_eq!(
+            parse_list::<f64>("TEST_BUCKETS", "0.1,fast")
+                .unwrap_err()
+                .to_string(),
+            "Invalid value for 'TEST_BUCKETS': 'fast'"
+        );
+    }
+
+    #[test]
+    fn buckets_must_increase() {
+        assert_eq!(
+            validate_buckets(&[0.1, 0.1, 1.0]),
+            Err("buckets must be strictly increasing")
+        );
+        assert_eq!(
+            validate_buckets(&[1.0, 0.5]),
+            Err("buckets must be strictly increasing")
+        );
+        assert_eq!(
+            validate_buckets(&[]),
+            Err("at least one bucket is required")
+        );
+        assert!(validate_buckets(DEFAULT_METRICS_BUCKETS).is_ok());
+    }
+
+    #[test]
+    fn log_format_is_read_from_the_value() {
+        assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("pretty".parse::<LogFormat>(), Ok(LogFormat::Pretty));
+        assert_eq!("xml".parse::<LogFormat>(), Err(()));
+    }
+
+    #[test]
+    fn log_format_defaults_by_build_profile() {
+        let expected = if cfg!(debug_assertions) {
+            LogFormat::Pretty
+        } else {
+            LogFormat::Json
+        };
+        assert_eq!(LogFormat::default(), expected);
+    }
+
+    #[test]
+    fn log_directives_must_parse() {
+        assert_eq!(log_directives(None).unwrap(), DEFAULT_LOG_DIRECTIVES);
+        assert!(log_directives(Some("axum_graphql=loud".into()))
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid value for 'RUST_LOG'"));
+    }
+
+    #[test]
+    fn exporter_follows_otel_exporter() {
+        assert!(matches!(
+            trace_exporter(Some("otlp"), true),
+            Ok(Some(TraceExporter::Otlp { .. }))
+        ));
+        assert!(matches!(
+            trace_exporter(Some("jaeger"), false),
+            Ok(Some(TraceExporter::Jaeger { .. }))
+        ));
+        assert_eq!(
+            trace_exporter(Some("zipkin"), false)
+                .unwrap_err()
+                .to_string(),
+            "Invalid value for 'OTEL_EXPORTER': 'zipkin'"
+        );
+    }
+
+    #[test]
+    fn exporter_defaults_to_jaeger_flag() {
+        assert!(matches!(
+            trace_exporter(None, true),
+            Ok(Some(TraceExporter::Jaeger { .. }))
+        ));
+        assert_eq!(trace_exporter(None, false), Ok(None));
+    }
+
+    #[test]
+    fn sample_ratio_must_be_a_fraction() {
+        assert_eq!(sample_ratio("0.25"), Ok(0.25));
+        assert_eq!(
+            sample_ratio("1.5").unwrap_err().to_string(),
+            "Invalid value for 'OTEL_TRACES_SAMPLER_ARG': '1.5'"
+        );
+    }
+}
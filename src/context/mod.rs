@@ -0,0 +1,90 @@
+use crate::auth::Authentication;
+use crate::layers::request_id::RequestId;
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequest, RequestParts},
+    http::{header, HeaderMap, HeaderName},
+};
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::Context;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+/// Headers resolvers may read. Anything else, cookies in particular, stays
+/// in the HTTP layer.
+const FORWARDED_HEADERS: [HeaderName; 3] = [
+    header::AUTHORIZATION,
+    header::USER_AGENT,
+    header::ACCEPT_LANGUAGE,
+];
+
+/// What resolvers get to know about the HTTP request an operation came in
+/// with; added to the data of every operation.
+#[derive(Clone, Debug)]
+pub(crate) struct RequestContext {
+    pub auth: Authentication,
+    pub headers: HeaderMap,
+    /// `None` unless the server is run with connect info.
+    pub remote_addr: Option<SocketAddr>,
+    /// The upstream span named by a valid W3C `traceparent` header.
+    pub trace_parent: Option<Context>,
+    /// `None` unless the app runs the `request_id` middleware.
+    pub request_id: Option<RequestId>,
+}
+
+impl RequestContext {
+    pub fn header(&self, name: HeaderName) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for RequestContext {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let auth = Authentication::from_request(req).await?;
+        let mut headers = HeaderMap::new();
+        for name in FORWARDED_HEADERS {
+            if let Some(value) = req.headers().get(&name) {
+                headers.insert(name, value.clone());
+            }
+        }
+        let remote_addr = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| *addr);
+        let trace_parent = extract_trace_parent(req.headers());
+        let request_id = req.extensions().get::<RequestId>().cloned();
+
+        Ok(RequestContext {
+            auth,
+            headers,
+            remote_addr,
+            trace_parent,
+            request_id,
+        })
+    }
+}
+
+/// Reads `traceparent` (and `tracestate`) into a context holding the remote
+/// span, or `None` when the header is missing or malformed.
+fn extract_trace_parent(headers: &HeaderMap) -> Option<Context> {
+    let cx = TraceContextPropagator::new()
+        .extract_with_context(&Context::new(), &HeaderExtractor(headers));
+    cx.span().span_context().is_valid().then(|| cx)
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key)?.to_str().ok()
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
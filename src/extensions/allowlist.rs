@@ -0,0 +1,120 @@
+use super::apq::{persisted_query_hash, query_hash};
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest,
+};
+use async_graphql::parser::types::Selection;
+use async_graphql::{Request, ServerError, ServerResult};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Only lets through operations whose document is in a pre-registered
+/// `{ "<sha256>": "<document>" }` map.
+///
+/// A request may send either the document or just its hash in the
+/// `persistedQuery` extension; in the latter case the document is taken from
+/// the list. Anything else fails with `QUERY_NOT_ALLOWED` before execution.
+pub(crate) struct AllowList {
+    documents: Arc<HashMap<String, String>>,
+    allow_introspection: bool,
+}
+
+impl AllowList {
+    /// Panics if the file can't be read, isn't a JSON object of strings, or
+    /// lists a document under the wrong hash.
+    pub fn load(path: &Path, allow_introspection: bool) -> Self {
+        let contents = fs::read_to_string(path).unwrap_or_else(|err| {
+            panic!("Could not read allow-list '{}': {}", path.display(), err)
+        });
+        let documents: HashMap<String, String> = serde_json::from_str(&contents)
+            .unwrap_or_else(|err| panic!("Invalid allow-list '{}': {}", path.display(), err));
+        for (hash, document) in &documents {
+            if !query_hash(document).eq_ignore_ascii_case(hash) {
+                panic!(
+                    "Invalid allow-list '{}': '{}' is not the hash of its document",
+                    path.display(),
+                    hash
+                );
+            }
+        }
+
+        AllowList {
+            documents: Arc::new(
+                documents
+                    .into_iter()
+                    .map(|(hash, document)| (hash.to_ascii_lowercase(), document))
+                    .collect(),
+            ),
+            allow_introspection,
+        }
+    }
+}
+
+impl ExtensionFactory for AllowList {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(AllowListExtension {
+            documents: self.documents.clone(),
+            allow_introspection: self.allow_introspection,
+        })
+    }
+}
+
+struct AllowListExtension {
+    documents: Arc<HashMap<String, String>>,
+    allow_introspection: bool,
+}
+
+#[async_trait]
+impl Extension for AllowListExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        mut request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        if request.query.is_empty() {
+            let document = persisted_query_hash(&request)
+                .and_then(|hash| self.documents.get(&hash.to_ascii_lowercase()));
+            match document {
+                Some(document) => request.query = document.clone(),
+                None => return Err(not_allowed()),
+            }
+        } else if !self.documents.contains_key(&query_hash(&request.query))
+            && !(self.allow_introspection && is_introspection(&request.query))
+        {
+            return Err(not_allowed());
+        }
+        next.run(ctx, request).await
+    }
+}
+
+fn not_allowed() -> ServerError {
+    let mut error = ServerError::new("query is not in the allow-list", None);
+    error
+        .extensions
+        .get_or_insert_with(Default::default)
+        .set("code", "QUERY_NOT_ALLOWED");
+    error
+}
+
+/// Whether every operation only selects `__schema`, `__type` or `__typename`.
+fn is_introspection(query: &str) -> bool {
+    let document = match async_graphql::parser::parse_query(query) {
+        Ok(document) => document,
+        Err(_) => return false,
+    };
+    document.operations.iter().all(|(_, operation)| {
+        operation
+            .node
+            .selection_set
+            .node
+            .items
+            .iter()
+            .all(|selection| match &selection.node {
+                Selection::Field(field) => field.node.name.node.starts_with("__"),
+                _ => false,
+            })
+    })
+}
@@ -0,0 +1,73 @@
+use async_graphql::extensions::apollo_persisted_queries::{CacheStorage, LruCacheStorage};
+use async_graphql::{Request, Response, ServerError, Value};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+
+const NOT_FOUND_MESSAGE: &str = "PersistedQueryNotFound";
+
+/// LRU storage for Automatic Persisted Queries that records cache hits and
+/// misses through the Prometheus recorder.
+#[derive(Clone)]
+pub(crate) struct MeteredCacheStorage(LruCacheStorage);
+
+impl MeteredCacheStorage {
+    pub fn new(capacity: usize) -> Self {
+        MeteredCacheStorage(LruCacheStorage::new(capacity))
+    }
+}
+
+#[async_trait]
+impl CacheStorage for MeteredCacheStorage {
+    async fn get(&self, key: String) -> Option<String> {
+        let query = self.0.get(key).await;
+        let result = if query.is_some() { "hit" } else { "miss" };
+        metrics::increment_counter!("graphql_apq_lookups_total", "result" => result);
+        query
+    }
+
+    async fn set(&self, key: String, query: String) {
+        self.0.set(key, query).await;
+    }
+}
+
+/// Rejects a request that registers a query under a hash that doesn't match
+/// it, before the APQ extension gets a chance to cache it.
+pub(crate) fn verify_persisted_query(request: &Request) -> Result<(), ServerError> {
+    if request.query.is_empty() {
+        return Ok(());
+    }
+    match persisted_query_hash(request) {
+        Some(hash) if !query_hash(&request.query).eq_ignore_ascii_case(hash) => {
+            Err(ServerError::new("provided sha does not match query", None))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The `sha256Hash` of the `persistedQuery` request extension, if present.
+pub(crate) fn persisted_query_hash(request: &Request) -> Option<&str> {
+    match request.extensions.get("persistedQuery") {
+        Some(Value::Object(persisted)) => match persisted.get("sha256Hash") {
+            Some(Value::String(hash)) => Some(hash),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Hex-encoded SHA-256 of a query document, as used by APQ.
+pub(crate) fn query_hash(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query.as_bytes()))
+}
+
+/// Adds the `PERSISTED_QUERY_NOT_FOUND` code Apollo clients look for.
+pub(crate) fn tag_not_found(response: &mut Response) {
+    for error in &mut response.errors {
+        if error.message == NOT_FOUND_MESSAGE {
+            error
+                .extensions
+                .get_or_insert_with(Default::default)
+                .set("code", "PERSISTED_QUERY_NOT_FOUND");
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextRequest,
+};
+use async_graphql::parser::types::{ExecutableDocument, Selection, SelectionSet};
+use async_graphql::{Pos, Response, ServerError, ServerResult, Variables};
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+
+/// Tags errors caused by malformed input values with the `BAD_USER_INPUT`
+/// code, so clients can tell them apart from resolver failures.
+///
+/// Literal arguments are checked during validation ("Invalid value for
+/// argument ...") while scalars such as `DateTime` and `UUID`, and argument
+/// validators, run when the field resolves ("Failed to parse ..."), in which
+/// case the error also carries the field path. Errors located at an
+/// argument's value name that argument in `extensions.field`.
+pub(crate) struct BadUserInput;
+
+impl ExtensionFactory for BadUserInput {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(BadUserInputExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct BadUserInputExtension {
+    /// Position of every argument value in the parsed document.
+    arguments: Mutex<Vec<(Pos, String)>>,
+}
+
+#[async_trait]
+impl Extension for BadUserInputExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let mut response = next.run(ctx).await;
+        let arguments = self.arguments.lock().unwrap();
+        for error in &mut response.errors {
+            if is_input_error(&error.message) {
+                let argument = argument_at(&arguments, error);
+                let extensions = error.extensions.get_or_insert_with(Default::default);
+                extensions.set("code", "BAD_USER_INPUT");
+                if let Some(argument) = argument {
+                    extensions.set("field", argument);
+                }
+            }
+        }
+        response
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let mut arguments = Vec::new();
+        for (_, operation) in document.operations.iter() {
+            collect_arguments(&operation.node.selection_set.node, &mut arguments);
+        }
+        for fragment in document.fragments.values() {
+            collect_arguments(&fragment.node.selection_set.node, &mut arguments);
+        }
+        *self.arguments.lock().unwrap() = arguments;
+        Ok(document)
+    }
+}
+
+fn is_input_error(message: &str) -> bool {
+    message.starts_with("Failed to parse \"")
+        || message.starts_with("Invalid value for argument")
+        || message.starts_with("Invalid value for variable")
+}
+
+fn collect_arguments(selection_set: &SelectionSet, arguments: &mut Vec<(Pos, String)>) {
+    for selection in &selection_set.items {
+        match &selection.node {
+            Selection::Field(field) => {
+                arguments.extend(
+                    field
+                        .node
+                        .arguments
+                        .iter()
+                        .map(|(name, value)| (value.pos, name.node.to_string())),
+                );
+                collect_arguments(&field.node.selection_set.node, arguments);
+            }
+            Selection::InlineFragment(fragment) => {
+                collect_arguments(&fragment.node.selection_set.node, arguments)
+            }
+            Selection::FragmentSpread(_) => {}
+        }
+    }
+}
+
+fn argument_at(arguments: &[(Pos, String)], error: &ServerError) -> Option<String> {
+    error.locations.iter().find_map(|location| {
+        arguments
+            .iter()
+            .find(|(pos, _)| pos == location)
+            .map(|(_, name)| name.clone())
+    })
+}
@@ -0,0 +1,77 @@
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextRequest};
+use async_graphql::{PathSegment, Response, ServerError};
+use async_trait::async_trait;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const INTERNAL_ERROR_CODE: &str = "INTERNAL_SERVER_ERROR";
+
+/// Replaces resolver errors that aren't known to be client-safe with a
+/// generic message and a correlation id, logging the original error.
+///
+/// Errors without a path come from parsing or validating the request and are
+/// always safe; resolver errors are safe when they carry a `code` other than
+/// `INTERNAL_SERVER_ERROR`, as `AppError` and `BadUserInput` set. Register
+/// this before the extensions that assign codes so it sees their output.
+pub(crate) struct MaskErrors;
+
+impl ExtensionFactory for MaskErrors {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(MaskErrorsExtension)
+    }
+}
+
+struct MaskErrorsExtension;
+
+#[async_trait]
+impl Extension for MaskErrorsExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let mut response = next.run(ctx).await;
+        for error in &mut response.errors {
+            if !is_client_safe(error) {
+                mask(error);
+            }
+        }
+        response
+    }
+}
+
+fn is_client_safe(error: &ServerError) -> bool {
+    if error.path.is_empty() {
+        return true;
+    }
+    let code = error
+        .extensions
+        .as_ref()
+        .and_then(|extensions| extensions.get("code"));
+    match code {
+        Some(async_graphql::Value::String(code)) => code != INTERNAL_ERROR_CODE,
+        _ => false,
+    }
+}
+
+fn mask(error: &mut ServerError) {
+    let correlation_id = Uuid::new_v4().to_string();
+    tracing::error!(
+        correlation_id = %correlation_id,
+        path = %format_path(&error.path),
+        error = %error.message,
+        "GraphQL resolver failed"
+    );
+
+    error.message = "Internal server error".into();
+    let mut extensions = async_graphql::ErrorExtensionValues::default();
+    extensions.set("code", INTERNAL_ERROR_CODE);
+    extensions.set("correlationId", correlation_id);
+    error.extensions = Some(extensions);
+}
+
+fn format_path(path: &[PathSegment]) -> String {
+    path.iter()
+        .map(|segment| match segment {
+            PathSegment::Field(name) => name.clone(),
+            PathSegment::Index(index) => index.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
@@ -0,0 +1,5 @@
+pub(crate) mod allowlist;
+pub(crate) mod apq;
+pub(crate) mod input_errors;
+pub(crate) mod mask_errors;
+pub(crate) mod selection_limits;
@@ -0,0 +1,174 @@
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextValidation,
+};
+use async_graphql::parser::types::{
+    ExecutableDocument, FragmentDefinition, Selection, SelectionSet,
+};
+use async_graphql::parser::Positioned;
+use async_graphql::{Name, ServerError, ServerResult, ValidationResult, Variables};
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Rejects operations nested deeper, more complex, or with more aliases or
+/// root fields than allowed, tagging each error with a code naming the
+/// limit. Aliases and root fields are caught by neither depth nor complexity
+/// (`a1: field a2: field ...`).
+///
+/// Aliases and root fields are checked as soon as the document is parsed, so
+/// an oversized operation fails before validation and before any resolver
+/// runs. Aliases in fragments count once per operation that spreads them.
+/// Depth and complexity are checked on the figures validation computes.
+#[derive(Clone, Copy)]
+pub(crate) struct SelectionLimits {
+    /// `None` disables the alias limit.
+    pub max_aliases: Option<usize>,
+    /// `None` disables the root field limit.
+    pub max_root_fields: Option<usize>,
+    /// `None` disables the depth limit.
+    pub max_depth: Option<usize>,
+    /// `None` disables the complexity limit.
+    pub max_complexity: Option<usize>,
+}
+
+impl ExtensionFactory for SelectionLimits {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(*self)
+    }
+}
+
+#[async_trait]
+impl Extension for SelectionLimits {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        for (_, operation) in document.operations.iter() {
+            let selection_set = &operation.node.selection_set.node;
+            if let Some(max_root_fields) = self.max_root_fields {
+                let root_fields = Counter::new(&document.fragments).root_fields(selection_set);
+                if root_fields > max_root_fields {
+                    return Err(limit_exceeded(
+                        format!(
+                            "operation selects {} root fields, more than the limit of {}",
+                            root_fields, max_root_fields
+                        ),
+                        "TOO_MANY_ROOT_FIELDS",
+                    ));
+                }
+            }
+            if let Some(max_aliases) = self.max_aliases {
+                let aliases = Counter::new(&document.fragments).aliases(selection_set);
+                if aliases > max_aliases {
+                    return Err(limit_exceeded(
+                        format!(
+                            "operation uses {} aliases, more than the limit of {}",
+                            aliases, max_aliases
+                        ),
+                        "TOO_MANY_ALIASES",
+                    ));
+                }
+            }
+        }
+        Ok(document)
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+        if self
+            .max_complexity
+            .map_or(false, |max| result.complexity > max)
+        {
+            let message = "Query is too complex.".to_owned();
+            return Err(vec![limit_exceeded(message, "QUERY_TOO_COMPLEX")]);
+        }
+        if self.max_depth.map_or(false, |max| result.depth > max) {
+            let message = "Query is nested too deep.".to_owned();
+            return Err(vec![limit_exceeded(message, "QUERY_TOO_DEEP")]);
+        }
+        Ok(result)
+    }
+}
+
+fn limit_exceeded(message: String, code: &str) -> ServerError {
+    let mut error = ServerError::new(message, None);
+    error
+        .extensions
+        .get_or_insert_with(Default::default)
+        .set("code", code);
+    error
+}
+
+/// Walks selection sets, expanding each fragment at most once.
+struct Counter<'a> {
+    fragments: &'a HashMap<Name, Positioned<FragmentDefinition>>,
+    visited: HashSet<&'a str>,
+}
+
+impl<'a> Counter<'a> {
+    fn new(fragments: &'a HashMap<Name, Positioned<FragmentDefinition>>) -> Self {
+        Counter {
+            fragments,
+            visited: HashSet::new(),
+        }
+    }
+
+    fn fragment(&mut self, name: &'a Name) -> Option<&'a SelectionSet> {
+        if !self.visited.insert(name.as_str()) {
+            return None;
+        }
+        let fragments = self.fragments;
+        fragments
+            .get(name)
+            .map(|fragment| &fragment.node.selection_set.node)
+    }
+
+    fn aliases(&mut self, selection_set: &'a SelectionSet) -> usize {
+        let mut count = 0;
+        for selection in &selection_set.items {
+            count += match &selection.node {
+                Selection::Field(field) => {
+                    usize::from(field.node.alias.is_some())
+                        + self.aliases(&field.node.selection_set.node)
+                }
+                Selection::InlineFragment(fragment) => {
+                    self.aliases(&fragment.node.selection_set.node)
+                }
+                Selection::FragmentSpread(spread) => {
+                    match self.fragment(&spread.node.fragment_name.node) {
+                        Some(selection_set) => self.aliases(selection_set),
+                        None => 0,
+                    }
+                }
+            };
+        }
+        count
+    }
+
+    fn root_fields(&mut self, selection_set: &'a SelectionSet) -> usize {
+        let mut count = 0;
+        for selection in &selection_set.items {
+            count += match &selection.node {
+                Selection::Field(_) => 1,
+                Selection::InlineFragment(fragment) => {
+                    self.root_fields(&fragment.node.selection_set.node)
+                }
+                Selection::FragmentSpread(spread) => {
+                    match self.fragment(&spread.node.fragment_name.node) {
+                        Some(selection_set) => self.root_fields(selection_set),
+                        None => 0,
+                    }
+                }
+            };
+        }
+        count
+    }
+}
@@ -0,0 +1,34 @@
+use axum::body::HttpBody;
+use axum::http::Response;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+
+/// Response extension that keeps a response uncompressed, for clients such as
+/// Prometheus scrapers that mishandle encodings.
+#[derive(Clone, Copy)]
+pub(crate) struct SkipCompression;
+
+#[derive(Clone, Copy)]
+struct NotSkipped;
+
+impl Predicate for NotSkipped {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        response.extensions().get::<SkipCompression>().is_none()
+    }
+}
+
+/// Compresses responses of at least `min_size` bytes, except images, gRPC and
+/// streamed (`multipart/mixed`, `text/event-stream`) bodies, whose chunks
+/// would otherwise be held back by the encoder.
+pub(crate) fn create_compression_layer(min_size: u16) -> CompressionLayer<impl Predicate> {
+    let predicate = SizeAbove::new(min_size)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::const_new("multipart/mixed"))
+        .and(NotForContentType::const_new("text/event-stream"))
+        .and(NotSkipped);
+    CompressionLayer::new().compress_when(predicate)
+}
@@ -0,0 +1,68 @@
+use axum::{
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const IN_FLIGHT_METRIC_NAME: &str = "graphql_requests_in_flight";
+
+/// Seconds a shed client is asked to wait; slots free up as fast as
+/// requests finish, so there is no better estimate.
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// Most GraphQL requests executed at once; registered as a request extension.
+#[derive(Clone)]
+pub(crate) struct ConcurrencyLimit(Arc<Semaphore>);
+
+impl ConcurrencyLimit {
+    pub fn new(max: usize) -> Self {
+        ConcurrencyLimit(Arc::new(Semaphore::new(max)))
+    }
+}
+
+/// A taken slot, counted in `graphql_requests_in_flight` until dropped.
+struct Slot(OwnedSemaphorePermit);
+
+impl Slot {
+    fn new(permit: OwnedSemaphorePermit) -> Self {
+        metrics::increment_gauge!(IN_FLIGHT_METRIC_NAME, 1.0);
+        Slot(permit)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        metrics::decrement_gauge!(IN_FLIGHT_METRIC_NAME, 1.0);
+    }
+}
+
+/// Answers `503 Service Unavailable` right away while `ConcurrencyLimit`
+/// requests are already running, rather than queueing behind them.
+pub(crate) async fn limit_concurrency<B>(req: Request<B>, next: Next<B>) -> Response {
+    let limit = match req.extensions().get::<ConcurrencyLimit>() {
+        Some(ConcurrencyLimit(limit)) => limit.clone(),
+        None => return next.run(req).await,
+    };
+    match limit.try_acquire_owned() {
+        Ok(permit) => {
+            let _slot = Slot::new(permit);
+            next.run(req).await
+        }
+        Err(_) => {
+            metrics::increment_counter!("graphql_requests_shed_total");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+                Json(json!({
+                    "error": "overloaded",
+                    "retry_after": RETRY_AFTER_SECS,
+                })),
+            )
+                .into_response()
+        }
+    }
+}
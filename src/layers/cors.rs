@@ -0,0 +1,14 @@
+use crate::config::CorsConfig;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+pub(crate) fn create_cors_layer(config: &CorsConfig) -> CorsLayer {
+    let layer = CorsLayer::new()
+        .allow_methods(config.allowed_methods.clone())
+        .allow_headers(config.allowed_headers.clone())
+        .allow_credentials(config.allow_credentials);
+
+    match &config.allowed_origins {
+        Some(origins) => layer.allow_origin(AllowOrigin::list(origins.clone())),
+        None => layer.allow_origin(Any),
+    }
+}
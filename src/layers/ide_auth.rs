@@ -0,0 +1,63 @@
+use crate::config::IdeCredentials;
+use crate::layers::metrics_auth::constant_time_eq;
+use crate::routes::is_operation;
+use axum::{
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+/// Basic auth credentials for the IDE page, registered as a request extension.
+#[derive(Clone)]
+pub(crate) struct IdeAuth(Arc<IdeCredentials>);
+
+impl IdeAuth {
+    pub fn new(credentials: &IdeCredentials) -> Self {
+        IdeAuth(Arc::new(credentials.clone()))
+    }
+
+    fn accepts(&self, authorization: &str) -> bool {
+        let credentials = match authorization
+            .strip_prefix("Basic ")
+            .and_then(|credentials| base64::decode(credentials).ok())
+        {
+            Some(credentials) => credentials,
+            None => return false,
+        };
+        let (user, password) = match credentials.iter().position(|&byte| byte == b':') {
+            Some(colon) => (&credentials[..colon], &credentials[colon + 1..]),
+            None => return false,
+        };
+        // Both halves are compared, so a wrong user name takes as long as a
+        // wrong password.
+        let user_matches = constant_time_eq(user, self.0.user.as_bytes());
+        let password_matches = constant_time_eq(password, self.0.password.as_bytes());
+        user_matches & password_matches
+    }
+}
+
+/// Answers `401 Unauthorized` with a basic auth challenge for IDE page
+/// requests without the `IdeAuth` credentials. Operations sent with GET pass
+/// through.
+pub(crate) async fn require_ide_credentials<B>(req: Request<B>, next: Next<B>) -> Response {
+    let auth = match req.extensions().get::<IdeAuth>() {
+        Some(auth) if !is_operation(req.uri().query()) => auth.clone(),
+        _ => return next.run(req).await,
+    };
+    let authorized = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .map_or(false, |authorization| auth.accepts(authorization));
+
+    if authorized {
+        next.run(req).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="GraphQL IDE""#)],
+        )
+            .into_response()
+    }
+}
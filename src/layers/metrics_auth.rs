@@ -0,0 +1,100 @@
+use crate::config::Cidr;
+use axum::{
+    extract::ConnectInfo,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Secret `/metrics` scrapers must present, either as a bearer token or as
+/// the password of basic auth (the user name is ignored).
+#[derive(Clone)]
+pub(crate) struct MetricsToken(Arc<str>);
+
+impl MetricsToken {
+    pub fn new(token: &str) -> Self {
+        MetricsToken(token.into())
+    }
+
+    fn accepts(&self, authorization: &HeaderValue) -> bool {
+        let authorization = match authorization.to_str() {
+            Ok(authorization) => authorization,
+            Err(_) => return false,
+        };
+        if let Some(token) = authorization.strip_prefix("Bearer ") {
+            return constant_time_eq(token.as_bytes(), self.0.as_bytes());
+        }
+        authorization
+            .strip_prefix("Basic ")
+            .and_then(|credentials| base64::decode(credentials).ok())
+            .and_then(|credentials| {
+                let colon = credentials.iter().position(|&byte| byte == b':')?;
+                Some(constant_time_eq(
+                    &credentials[colon + 1..],
+                    self.0.as_bytes(),
+                ))
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Answers an empty `401 Unauthorized` unless the request carries the
+/// `MetricsToken`.
+pub(crate) async fn require_metrics_token<B>(req: Request<B>, next: Next<B>) -> Response {
+    let token = match req.extensions().get::<MetricsToken>() {
+        Some(token) => token.clone(),
+        None => return next.run(req).await,
+    };
+    let authorized = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .map_or(false, |authorization| token.accepts(authorization));
+
+    if authorized {
+        next.run(req).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Bearer, Basic realm="metrics""#)],
+        )
+            .into_response()
+    }
+}
+
+/// Peer networks allowed to scrape `/metrics`.
+#[derive(Clone)]
+pub(crate) struct MetricsAllowList(Arc<[Cidr]>);
+
+impl MetricsAllowList {
+    pub fn new(cidrs: &[Cidr]) -> Self {
+        MetricsAllowList(cidrs.into())
+    }
+}
+
+/// Answers an empty `403 Forbidden` unless the peer address is in the
+/// `MetricsAllowList`; peers of unknown address are refused.
+pub(crate) async fn require_metrics_peer<B>(req: Request<B>, next: Next<B>) -> Response {
+    let allowed = match req.extensions().get::<MetricsAllowList>() {
+        Some(MetricsAllowList(cidrs)) => req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or(false, |ConnectInfo(addr)| {
+                cidrs.iter().any(|cidr| cidr.contains(addr.ip()))
+            }),
+        None => true,
+    };
+
+    if allowed {
+        next.run(req).await
+    } else {
+        StatusCode::FORBIDDEN.into_response()
+    }
+}
+
+/// Compares without returning early, so response times don't reveal how much
+/// of a guess was right.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,13 @@
+//! Middleware. Each one reads its settings from a request extension and
+//! lets requests pass through when that extension is not registered, so a
+//! layer is switched off by leaving its extension out; `auth::authenticate`
+//! works the same way.
+
+pub(crate) mod compression;
+pub(crate) mod concurrency;
+pub(crate) mod cors;
+pub(crate) mod ide_auth;
+pub(crate) mod metrics_auth;
+pub(crate) mod rate_limit;
+pub(crate) mod request_id;
+pub(crate) mod timeout;
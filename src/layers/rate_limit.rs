@@ -0,0 +1,392 @@
+use crate::config::Cidr;
+use axum::{
+    extract::ConnectInfo,
+    http::{header, HeaderMap, HeaderName, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most clients tracked at once, so a flood of unique addresses or keys
+/// can't grow the bucket map without bound.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token bucket per client: every client may burst up to `burst` requests and
+/// regains `rps` requests per second.
+#[derive(Clone)]
+pub(crate) struct RateLimiter {
+    rps: f64,
+    burst: f64,
+    max_clients: usize,
+    keys: Arc<ClientKeys>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+/// How a request is told apart from other clients' requests.
+#[derive(Default)]
+pub(crate) struct ClientKeys {
+    /// Peers whose `X-Forwarded-For` is believed; the client is the nearest
+    /// address in it that isn't one of them.
+    pub trusted_proxies: Vec<Cidr>,
+    /// Believes `X-Forwarded-For` from peers without an address, which are
+    /// the local proxy when the server listens on a unix socket.
+    pub trust_unaddressed_peers: bool,
+    /// Header whose value identifies the client instead of its address, when
+    /// it is one of `api_keys`.
+    pub api_key_header: Option<HeaderName>,
+    /// SHA-256 of every accepted API key. Other values get no bucket of their
+    /// own, so made-up keys can't add to a client's budget.
+    pub api_keys: HashSet<[u8; 32]>,
+}
+
+impl ClientKeys {
+    pub fn with_api_keys<'a>(
+        self,
+        header: Option<HeaderName>,
+        keys: impl IntoIterator<Item = &'a String>,
+    ) -> Self {
+        ClientKeys {
+            api_key_header: header,
+            api_keys: keys
+                .into_iter()
+                .map(|key| Sha256::digest(key.as_bytes()).into())
+                .collect(),
+            ..self
+        }
+    }
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+enum ClientKey {
+    Ip(IpAddr),
+    /// SHA-256 of the API key, so long keys don't grow the map.
+    ApiKey([u8; 32]),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Position in `Buckets::by_use`.
+    used: u64,
+}
+
+/// Buckets with their clients ordered by last use, so the least recently
+/// used one is found without scanning the map.
+#[derive(Default)]
+struct Buckets {
+    by_client: HashMap<ClientKey, Bucket>,
+    by_use: BTreeMap<u64, ClientKey>,
+    uses: u64,
+}
+
+impl RateLimiter {
+    pub fn new(rps: usize, burst: usize) -> Self {
+        RateLimiter {
+            rps: rps as f64,
+            burst: burst.max(1) as f64,
+            max_clients: MAX_TRACKED_CLIENTS,
+            keys: Arc::default(),
+            buckets: Arc::default(),
+        }
+    }
+
+    pub fn keyed_by(self, keys: ClientKeys) -> Self {
+        RateLimiter {
+            keys: Arc::new(keys),
+            ..self
+        }
+    }
+
+    /// The bucket `headers` and the peer address `peer` are counted against.
+    fn client_key(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> ClientKey {
+        let api_key = self
+            .keys
+            .api_key_header
+            .as_ref()
+            .and_then(|name| headers.get(name))
+            .filter(|value| !value.is_empty());
+        if let Some(api_key) = api_key {
+            let digest: [u8; 32] = Sha256::digest(api_key.as_bytes()).into();
+            if self.keys.api_keys.contains(&digest) {
+                return ClientKey::ApiKey(digest);
+            }
+        }
+        let trusted_peer = match peer {
+            Some(ip) => self.is_trusted(ip),
+            None => self.keys.trust_unaddressed_peers,
+        };
+        let forwarded = if trusted_peer {
+            self.forwarded_client(headers)
+        } else {
+            None
+        };
+        ClientKey::Ip(
+            forwarded
+                .or(peer)
+                .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        )
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.keys
+            .trusted_proxies
+            .iter()
+            .any(|cidr| cidr.contains(ip))
+    }
+
+    /// The client address in `X-Forwarded-For`. Proxies append the address
+    /// they got the request from, so the nearest hops are trusted proxies and
+    /// the first other address is the client's.
+    fn forwarded_client(&self, headers: &HeaderMap) -> Option<IpAddr> {
+        let hops: Vec<IpAddr> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+        hops.iter()
+            .rev()
+            .find(|ip| !self.is_trusted(**ip))
+            .or_else(|| hops.first())
+            .copied()
+    }
+
+    /// Takes a token for `client`, or returns how long until one is available.
+    fn acquire(&self, client: ClientKey, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let buckets = &mut *buckets;
+        if buckets.by_client.len() >= self.max_clients && !buckets.by_client.contains_key(&client) {
+            buckets.evict_least_recently_used();
+        }
+        buckets.uses += 1;
+        let used = buckets.uses;
+        let bucket = buckets.by_client.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+            used,
+        });
+        buckets.by_use.remove(&bucket.used);
+        buckets.by_use.insert(used, client);
+        bucket.used = used;
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rps).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rps))
+        }
+    }
+}
+
+impl Buckets {
+    /// Makes room for a new client by dropping the least recently used one.
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self.by_use.keys().next().copied();
+        if let Some(client) = oldest.and_then(|used| self.by_use.remove(&used)) {
+            metrics::increment_counter!("http_rate_limit_evictions_total");
+            self.by_client.remove(&client);
+        }
+    }
+}
+
+/// Answers `429 Too Many Requests` once the client has used up its tokens.
+/// Without `ConnectInfo` or a trusted forwarded address all clients share
+/// one bucket.
+pub(crate) async fn rate_limit<B>(req: Request<B>, next: Next<B>) -> Response {
+    let limiter = match req.extensions().get::<RateLimiter>() {
+        Some(limiter) => limiter.clone(),
+        None => return next.run(req).await,
+    };
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client = limiter.client_key(req.headers(), peer);
+
+    match limiter.acquire(client, Instant::now()) {
+        Ok(()) => next.run(req).await,
+        Err(wait) => {
+            metrics::increment_counter!("http_rate_limited_requests_total");
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                Json(json!({
+                    "error": "too_many_requests",
+                    "retry_after": retry_after,
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokens_refill_over_time() {
+        let limiter = RateLimiter::new(2, 2);
+        let ip = ClientKey::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let start = Instant::now();
+
+        assert!(limiter.acquire(ip, start).is_ok());
+        assert!(limiter.acquire(ip, start).is_ok());
+        assert_eq!(limiter.acquire(ip, start), Err(Duration::from_millis(500)));
+        assert!(limiter
+            .acquire(ip, start + Duration::from_millis(500))
+            .is_ok());
+    }
+
+    #[test]
+    fn burst_is_independent_of_the_rate() {
+        let limiter = RateLimiter::new(1, 3);
+        let ip = ClientKey::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.acquire(ip, start).is_ok());
+        }
+        assert_eq!(limiter.acquire(ip, start), Err(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn tracked_clients_are_bounded() {
+        let limiter = RateLimiter {
+            max_clients: 2,
+            ..RateLimiter::new(1, 1)
+        };
+        let start = Instant::now();
+        let client = |last: u8| ClientKey::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, last)));
+
+        assert!(limiter.acquire(client(1), start).is_ok());
+        assert!(limiter
+            .acquire(client(2), start + Duration::from_millis(100))
+            .is_ok());
+        assert!(limiter
+            .acquire(client(3), start + Duration::from_millis(200))
+            .is_ok());
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_client.len(), 2);
+        assert!(!buckets.by_client.contains_key(&client(1)));
+    }
+
+    #[test]
+    fn recently_used_clients_are_kept() {
+        let limiter = RateLimiter {
+            max_clients: 2,
+            ..RateLimiter::new(1, 5)
+        };
+        let start = Instant::now();
+        let client = |last: u8| ClientKey::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, last)));
+
+        assert!(limiter.acquire(client(1), start).is_ok());
+        assert!(limiter.acquire(client(2), start).is_ok());
+        assert!(limiter.acquire(client(1), start).is_ok());
+        assert!(limiter.acquire(client(3), start).is_ok());
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.by_client.contains_key(&client(1)));
+        assert!(!buckets.by_client.contains_key(&client(2)));
+        assert_eq!(buckets.by_use.len(), 2);
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (HeaderName::from_static(name), value.parse().unwrap()))
+            .collect()
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_address_is_only_believed_from_trusted_proxies() {
+        let limiter = RateLimiter::new(1, 1).keyed_by(ClientKeys {
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+            ..ClientKeys::default()
+        });
+        let forwarded = headers(&[("x-forwarded-for", "203.0.113.7, 10.0.0.3")]);
+
+        assert_eq!(
+            limiter.client_key(&forwarded, Some(ip("10.0.0.2"))),
+            ClientKey::Ip(ip("203.0.113.7"))
+        );
+        assert_eq!(
+            limiter.client_key(&forwarded, Some(ip("198.51.100.1"))),
+            ClientKey::Ip(ip("198.51.100.1"))
+        );
+        assert_eq!(
+            limiter.client_key(&HeaderMap::new(), Some(ip("10.0.0.2"))),
+            ClientKey::Ip(ip("10.0.0.2"))
+        );
+    }
+
+    #[test]
+    fn unaddressed_peers_are_trusted_only_when_configured() {
+        let forwarded = headers(&[("x-forwarded-for", "203.0.113.7")]);
+
+        let limiter = RateLimiter::new(1, 1);
+        assert_eq!(
+            limiter.client_key(&forwarded, None),
+            ClientKey::Ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+        );
+        let limiter = RateLimiter::new(1, 1).keyed_by(ClientKeys {
+            trust_unaddressed_peers: true,
+            ..ClientKeys::default()
+        });
+        assert_eq!(
+            limiter.client_key(&forwarded, None),
+            ClientKey::Ip(ip("203.0.113.7"))
+        );
+    }
+
+    #[test]
+    fn api_keys_get_their_own_buckets() {
+        let keys = vec!["first".to_string(), "second".to_string()];
+        let limiter = RateLimiter::new(1, 1).keyed_by(
+            ClientKeys::default().with_api_keys(Some(HeaderName::from_static("x-api-key")), &keys),
+        );
+        let peer = Some(ip("10.0.0.2"));
+        let first = limiter.client_key(&headers(&[("x-api-key", "first")]), peer);
+        let second = limiter.client_key(&headers(&[("x-api-key", "second")]), peer);
+        let start = Instant::now();
+
+        assert_ne!(first, second);
+        assert!(limiter.acquire(first, start).is_ok());
+        assert!(limiter.acquire(second, start).is_ok());
+        assert!(limiter.acquire(first, start).is_err());
+        assert_eq!(
+            limiter.client_key(&HeaderMap::new(), peer),
+            ClientKey::Ip(ip("10.0.0.2"))
+        );
+    }
+
+    #[test]
+    fn unknown_api_keys_count_against_the_address() {
+        let keys = vec!["first".to_string()];
+        let limiter = RateLimiter::new(1, 1).keyed_by(
+            ClientKeys::default().with_api_keys(Some(HeaderName::from_static("x-api-key")), &keys),
+        );
+        let peer = Some(ip("10.0.0.2"));
+
+        assert_eq!(
+            limiter.client_key(&headers(&[("x-api-key", "made-up")]), peer),
+            ClientKey::Ip(ip("10.0.0.2"))
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use axum::{
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest incoming id that is reused as is.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Identifies one request across services and log lines; stored in the
+/// request extensions by `request_id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RequestId(pub String);
+
+impl RequestId {
+    /// Reuses the caller's id when it is short, printable ASCII, and
+    /// generates a UUIDv4 otherwise.
+    fn from_header(value: Option<&HeaderValue>) -> Self {
+        let incoming = value
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_acceptable(id));
+        match incoming {
+            Some(id) => RequestId(id.to_owned()),
+            None => RequestId(Uuid::new_v4().to_string()),
+        }
+    }
+}
+
+fn is_acceptable(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+/// Tags the request with a `RequestId` and echoes it in `x-request-id`.
+pub(crate) async fn request_id<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let id = RequestId::from_header(req.headers().get(REQUEST_ID_HEADER));
+    req.extensions_mut().insert(id.clone());
+
+    let mut response = next.run(req).await;
+    // Only printable ASCII is kept, so the id is always a valid header value.
+    if let Ok(value) = HeaderValue::from_str(&id.0) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id_for(value: &str) -> RequestId {
+        RequestId::from_header(Some(&HeaderValue::from_str(value).unwrap()))
+    }
+
+    #[test]
+    fn incoming_ids_are_reused() {
+        assert_eq!(id_for("abc-123"), RequestId("abc-123".into()));
+    }
+
+    #[test]
+    fn unusable_ids_are_replaced() {
+        for value in ["", "has space", &"a".repeat(MAX_REQUEST_ID_LENGTH + 1)] {
+            let id = id_for(value);
+            assert_ne!(id.0, value);
+            assert!(Uuid::parse_str(&id.0).is_ok(), "{}", id.0);
+        }
+        assert!(Uuid::parse_str(&RequestId::from_header(None).0).is_ok());
+    }
+}
@@ -0,0 +1,22 @@
+use crate::layers::request_id::RequestId;
+use axum::{extract::Extension, http::StatusCode, BoxError};
+use tower::timeout::error::Elapsed;
+
+/// Maps errors from `tower::timeout` to responses, since axum only routes to
+/// infallible services.
+pub(crate) async fn handle_timeout_error(
+    request_id: Option<Extension<RequestId>>,
+    err: BoxError,
+) -> (StatusCode, String) {
+    if err.is::<Elapsed>() {
+        (StatusCode::REQUEST_TIMEOUT, "request timed out".to_string())
+    } else {
+        let message = match request_id {
+            Some(Extension(RequestId(id))) => {
+                format!("unhandled internal error: {} (request id {})", err, id)
+            }
+            None => format!("unhandled internal error: {}", err),
+        };
+        (StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
@@ -0,0 +1,129 @@
+use crate::model::{Author, AuthorStore, Note, NoteStore};
+use async_graphql::dataloader::Loader;
+use async_graphql::ID;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Batches note lookups by id.
+///
+/// A fresh loader is registered for every request in `graphql_handler`, and
+/// `NoteQuery::note` resolves through it, so aliased `note` fields in one
+/// document are served by a single store read.
+pub(crate) struct NoteLoader {
+    store: NoteStore,
+    batches: AtomicUsize,
+}
+
+impl NoteLoader {
+    pub fn new(store: NoteStore) -> Self {
+        NoteLoader {
+            store,
+            batches: AtomicUsize::new(0),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn batches(&self) -> usize {
+        self.batches.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Loader<ID> for NoteLoader {
+    type Value = Note;
+    type Error = Infallible;
+
+    async fn load(&self, keys: &[ID]) -> Result<HashMap<ID, Self::Value>, Self::Error> {
+        self.batches.fetch_add(1, Ordering::SeqCst);
+        Ok(self.store.get_many(keys).await)
+    }
+}
+
+/// Batches author lookups for `Note::author`.
+///
+/// Unlike `NoteLoader` it is registered once in the schema data, with its
+/// batching knobs taken from `GraphQLConfig`.
+pub(crate) struct AuthorLoader {
+    store: AuthorStore,
+    batches: AtomicUsize,
+    keys: AtomicUsize,
+}
+
+impl AuthorLoader {
+    pub fn new(store: AuthorStore) -> Self {
+        AuthorLoader {
+            store,
+            batches: AtomicUsize::new(0),
+            keys: AtomicUsize::new(0),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn batches(&self) -> usize {
+        self.batches.load(Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub fn keys(&self) -> usize {
+        self.keys.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Loader<ID> for AuthorLoader {
+    type Value = Author;
+    type Error = Infallible;
+
+    async fn load(&self, keys: &[ID]) -> Result<HashMap<ID, Self::Value>, Self::Error> {
+        self.batches.fetch_add(1, Ordering::SeqCst);
+        self.keys.fetch_add(keys.len(), Ordering::SeqCst);
+        Ok(self.store.get_many(keys).await)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_graphql::dataloader::DataLoader;
+    use futures_util::future::join_all;
+
+    #[tokio::test]
+    async fn concurrent_loads_are_batched() {
+        let store = NoteStore::default();
+        let mut ids = Vec::new();
+        for n in 0..5 {
+            ids.push(
+                store
+                    .insert(format!("note {}", n), String::new(), None, None)
+                    .await
+                    .id,
+            );
+        }
+        let loader = DataLoader::new(NoteLoader::new(store), tokio::spawn);
+
+        let notes = join_all(ids.iter().cloned().map(|id| loader.load_one(id))).await;
+
+        assert!(notes.iter().all(|note| matches!(note, Ok(Some(_)))));
+        assert_eq!(loader.loader().batches(), 1);
+    }
+
+    #[tokio::test]
+    async fn repeated_author_keys_are_deduplicated() {
+        let store = AuthorStore::default();
+        let mut authors = Vec::new();
+        for n in 0..3 {
+            authors.push(store.insert(format!("author {}", n), None).await.id);
+        }
+        let loader = DataLoader::new(AuthorLoader::new(store), tokio::spawn);
+
+        // Ten notes sharing three authors.
+        let keys = (0..10).map(|n| authors[n % authors.len()].clone());
+        let loaded = join_all(keys.map(|id| loader.load_one(id))).await;
+
+        assert!(loaded.iter().all(|author| matches!(author, Ok(Some(_)))));
+        assert_eq!(loader.loader().batches(), 1);
+        assert_eq!(loader.loader().keys(), 3);
+    }
+}
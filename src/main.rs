@@ -1,35 +1,82 @@
-use async_graphql::{EmptyMutation, EmptySubscription, Schema};
-use axum::{extract::Extension, middleware, routing::get, Router, Server};
+use async_graphql::SDLExportOptions;
+use axum::{
+    body::Body,
+    error_handling::HandleErrorLayer,
+    extract::Extension,
+    handler::Handler,
+    http::Request,
+    middleware::{self, Next},
+    routing::{get, post},
+    Router, Server,
+};
 
-use tracing::info;
+use tower::ServiceBuilder;
+use tracing::{info, warn};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::Registry;
 
-use std::future::ready;
-use std::net::SocketAddr;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use std::future::{ready, Future};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use dotenv::dotenv;
 
 use tokio::signal;
 
+mod auth;
+mod config;
+mod context;
+mod extensions;
+mod layers;
+mod loaders;
 mod routes;
 mod model;
 mod observability;
+mod shutdown;
 
-use crate::routes::{graphql_handler, graphql_playground, health};
+use crate::auth::{authenticate, AuthRequired, JwtKeys};
+use crate::config::{Config, ServerConfig, TlsConfig, UdsConfig};
+use crate::layers::compression::{create_compression_layer, SkipCompression};
+use crate::layers::concurrency::{limit_concurrency, ConcurrencyLimit};
+use crate::layers::cors::create_cors_layer;
+use crate::layers::ide_auth::{require_ide_credentials, IdeAuth};
+use crate::layers::metrics_auth::{
+    require_metrics_peer, require_metrics_token, MetricsAllowList, MetricsToken,
+};
+use crate::layers::rate_limit::{rate_limit, ClientKeys, RateLimiter};
+use crate::layers::request_id::request_id;
+use crate::layers::timeout::handle_timeout_error;
+use crate::routes::{
+    graphql_get, graphql_get_query, graphql_handler, graphql_schema, graphql_subscription, health,
+    liveness, method_not_allowed, not_found, readiness, BatchLimit, BodyLimit, ExecutionTimeout,
+    HealthChecks, IdeSettings, version, LifecycleCheck, ResponseCache, SchemaCheck, StoreCheck,
+    UploadLimits, WsLimits,
+};
+use crate::observability::logging::{create_env_filter, create_fmt_layer};
 use crate::observability::metrics::{create_prometheus_recorder, track_metrics};
-use crate::observability::tracing::create_tracer_from_env;
-use crate::model::QueryRoot;
+use crate::observability::tracing::create_tracer;
+use crate::model::{create_schema, NoteStore, ServiceSchema};
+use crate::shutdown::{OpenRequests, Shutdown, ShutdownSignal};
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8000));
-    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription).finish();
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let store = NoteStore::default();
+    let schema = create_schema(&config.graphql, store.clone());
     let registry = Registry::default()
-            .with(tracing_subscriber::fmt::layer().pretty());
+            .with(create_env_filter(&config.telemetry.log_directives))
+            .with(create_fmt_layer(config.telemetry.log_format));
     
-    match create_tracer_from_env() {
+    match create_tracer(&config.telemetry) {
         Some(tracer) => registry
             .with(tracing_opentelemetry::layer().with_tracer(tracer))
             .try_init()
@@ -41,23 +88,361 @@ async fn main() {
 
     info!("Server starting");
 
-    let app = create_app(schema);
-    Server::bind(&addr)
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+    let shutdown = Shutdown::new();
+    let app = create_app(&config, schema, store, shutdown.signal());
+    // The schema and tracer are set up, so start passing readiness checks.
+    shutdown.mark_ready();
+    let listener = match (&config.server.uds, &config.server.tls) {
+        #[cfg(unix)]
+        (Some(uds), _) => {
+            let unix = bind_unix(uds);
+            info!("Listening on {}", uds.path.display());
+            Listener::Unix(unix, SocketFile(uds.path.clone()))
+        }
+        #[cfg(not(unix))]
+        (Some(_), _) => panic!("'LISTEN_UDS' is only supported on unix"),
+        (None, Some(tls)) => {
+            let (tcp, addr) = bind(&config.server);
+            info!("Listening on {}", addr);
+            let rustls = load_tls_config(tls).await;
+            #[cfg(unix)]
+            tokio::spawn(reload_tls_on_sighup(
+                rustls.clone(),
+                tls.cert_path.clone(),
+                tls.key_path.clone(),
+            ));
+            Listener::Tls(tcp, rustls)
+        }
+        (None, None) => {
+            let (tcp, addr) = bind(&config.server);
+            info!("Listening on {}", addr);
+            Listener::Plain(tcp)
+        }
+    };
+    serve(
+        listener,
+        app,
+        shutdown,
+        config.server.lame_duck,
+        config.server.shutdown_timeout,
+        shutdown_signal(),
+    )
+    .await;
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Binds the configured address, returning the listener with the address it
+/// actually got; with `PORT=0` that carries the port the OS picked.
+fn bind(server: &ServerConfig) -> (TcpListener, SocketAddr) {
+    let addr = server.socket_addr();
+    let tcp = TcpListener::bind(addr)
+        .unwrap_or_else(|err| panic!("Failed to bind to '{}': {}", addr, err));
+    let bound = tcp.local_addr().expect("Failed to read the bound address");
+    (tcp, bound)
+}
+
+/// How `serve` accepts connections.
+enum Listener {
+    Plain(TcpListener),
+    Tls(TcpListener, RustlsConfig),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, SocketFile),
+}
+
+/// Path of a unix socket the server bound, unlinked once it stops serving.
+#[cfg(unix)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Binds the socket at `uds.path`, replacing a socket file left behind by a
+/// previous run, and applies `uds.mode` to it.
+#[cfg(unix)]
+fn bind_unix(uds: &UdsConfig) -> tokio::net::UnixListener {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let path = &uds.path;
+    let stale = std::fs::symlink_metadata(path).map_or(false, |meta| meta.file_type().is_socket());
+    if stale {
+        std::fs::remove_file(path).unwrap_or_else(|err| {
+            panic!("Failed to remove stale socket '{}': {}", path.display(), err)
+        });
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .unwrap_or_else(|err| panic!("Failed to bind to '{}': {}", path.display(), err));
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(uds.mode))
+        .unwrap_or_else(|err| panic!("Failed to set permissions on '{}': {}", path.display(), err));
+    listener
+}
+
+/// Reads the PEM certificate chain and private key, panicking with the path
+/// of whichever file can't be used.
+async fn load_tls_config(tls: &TlsConfig) -> RustlsConfig {
+    let read = |path: &Path| {
+        std::fs::read(path).unwrap_or_else(|err| {
+            panic!("Failed to read TLS file '{}': {}", path.display(), err)
+        })
+    };
+    let cert = read(&tls.cert_path);
+    let key = read(&tls.key_path);
+    RustlsConfig::from_pem(cert, key).await.unwrap_or_else(|err| {
+        panic!(
+            "Invalid TLS certificate '{}' or key '{}': {}",
+            tls.cert_path.display(),
+            tls.key_path.display(),
+            err
+        )
+    })
 }
 
-fn create_app(schema: Schema<QueryRoot, EmptyMutation, EmptySubscription>) -> Router {
-    let prometheus_recorder = create_prometheus_recorder();
+/// Rereads the certificate and key on every `SIGHUP`, so renewed certificates
+/// are picked up without a restart. A failed reload keeps serving the old
+/// ones.
+#[cfg(unix)]
+async fn reload_tls_on_sighup(rustls: RustlsConfig, cert_path: PathBuf, key_path: PathBuf) {
+    let mut hangups = signal::unix::signal(signal::unix::SignalKind::hangup())
+        .expect("failed to install signal handler");
+    while hangups.recv().await.is_some() {
+        match rustls.reload_from_pem_file(&cert_path, &key_path).await {
+            Ok(()) => info!("Reloaded TLS certificate '{}'", cert_path.display()),
+            Err(err) => warn!(
+                "Keeping the current TLS certificate; reloading '{}' failed: {}",
+                cert_path.display(),
+                err
+            ),
+        }
+    }
+}
+
+/// Serves `app` until `signal` resolves and the open requests and WebSocket
+/// connections have finished, or until `drain_timeout` has passed since, in
+/// which case whatever is left is dropped. Returns how many requests were
+/// still open when `drain_timeout` cut them off.
+///
+/// For `lame_duck` after the signal, health already fails but new requests
+/// are still accepted; the drain starts after it.
+async fn serve<F>(
+    listener: Listener,
+    app: Router,
+    shutdown: Shutdown,
+    lame_duck: Duration,
+    drain_timeout: Duration,
+    signal: F,
+) -> usize
+where
+    F: Future<Output = ()>,
+{
+    let open = OpenRequests::default();
+    let app = app.layer(middleware::from_fn({
+        let open = open.clone();
+        move |req: Request<Body>, next: Next<Body>| {
+            let request = open.start();
+            async move {
+                let response = next.run(req).await;
+                drop(request);
+                response
+            }
+        }
+    }));
+    let deadline = shutdown.deadline(lame_duck + drain_timeout);
+    let drain = async move {
+        let triggered = async {
+            signal.await;
+            shutdown.trigger();
+            if !lame_duck.is_zero() {
+                info!("Draining connections in {:?}", lame_duck);
+                tokio::time::sleep(lame_duck).await;
+            }
+        };
+        match listener {
+            Listener::Plain(tcp) => Server::from_tcp(tcp)
+                .unwrap()
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(triggered)
+                .await
+                .unwrap(),
+            Listener::Tls(tcp, tls) => {
+                let handle = Handle::new();
+                let server = axum_server::from_tcp_rustls(tcp, tls)
+                    .handle(handle.clone())
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+                tokio::pin!(server);
+                tokio::select! {
+                    result = &mut server => result.unwrap(),
+                    _ = triggered => {
+                        handle.graceful_shutdown(None);
+                        server.await.unwrap();
+                    }
+                }
+            }
+            // Unix peers have no address, so requests carry no `ConnectInfo`;
+            // the rate limit keys on `X-Forwarded-For` instead.
+            #[cfg(unix)]
+            Listener::Unix(unix, _socket_file) => {
+                let accept = hyper::server::accept::poll_fn(move |cx| {
+                    unix.poll_accept(cx)
+                        .map(|accepted| Some(accepted.map(|(stream, _)| stream)))
+                });
+                Server::builder(accept)
+                    .serve(app.into_make_service())
+                    .with_graceful_shutdown(triggered)
+                    .await
+                    .unwrap()
+            }
+        }
+
+        // Upgraded WebSocket connections are no longer tracked by the server,
+        // so wait for them to send their close frames before flushing traces.
+        shutdown.drained().await;
+    };
+    tokio::select! {
+        _ = drain => 0,
+        _ = deadline => {
+            let cut_off = open.count();
+            warn!(
+                "Dropping {} requests still running {:?} after shutdown",
+                cut_off, drain_timeout
+            );
+            cut_off
+        }
+    }
+}
 
-    Router::new()
+fn create_app(
+    config: &Config,
+    schema: ServiceSchema,
+    store: NoteStore,
+    shutdown: ShutdownSignal,
+) -> Router {
+    let prometheus_recorder = create_prometheus_recorder(&config.metrics.buckets);
+    let sdl_options = if config.graphql.federation {
+        SDLExportOptions::new().federation()
+    } else {
+        SDLExportOptions::new()
+    };
+    let graphql = if config.graphql.disable_introspection {
+        get(graphql_get_query).post(graphql_handler)
+    } else {
+        get(graphql_get.layer(middleware::from_fn(require_ide_credentials))).post(graphql_handler)
+    };
+    let mut app = Router::new();
+    if !config.graphql.disable_introspection {
+        app = app.route("/schema", get(graphql_schema).layer(Extension(sdl_options)));
+    }
+    app = app
         .route("/health", get(health))
-        .route("/", get(graphql_playground).post(graphql_handler))
-        .route("/metrics", get(move || ready(prometheus_recorder.render())))
+        .route("/health/live", get(liveness))
+        .route("/health/ready", get(readiness))
+        .route("/livez", get(liveness))
+        .route("/readyz", get(readiness))
+        .route("/version", get(version))
+        .route("/info", get(version))
+        .route(
+            "/metrics",
+            get(move || ready((Extension(SkipCompression), prometheus_recorder.render())))
+                .layer(middleware::from_fn(require_metrics_token))
+                .layer(middleware::from_fn(require_metrics_peer)),
+        )
+        // GraphQL requests time out inside `graphql_handler` instead, so they
+        // are answered with a GraphQL error rather than a bare `408`.
+        .route_layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_timeout_error))
+                .timeout(config.graphql.request_timeout),
+        )
+        .route(
+            "/",
+            graphql
+                .layer(middleware::from_fn(limit_concurrency))
+                .layer(middleware::from_fn(authenticate))
+                .layer(middleware::from_fn(rate_limit)),
+        )
+        .route(
+            "/ws",
+            // Authenticated by `connection_init`, which browsers can send a
+            // token with.
+            get(graphql_subscription).layer(middleware::from_fn(rate_limit)),
+        )
         .route_layer(middleware::from_fn(track_metrics))
+        .fallback(
+            not_found
+                .layer(middleware::from_fn(track_metrics))
+                .into_service(),
+        )
+        .layer(middleware::from_fn(method_not_allowed));
+    if let Some(rps) = config.graphql.rate_limit_rps {
+        let keys = ClientKeys {
+            trusted_proxies: config.graphql.rate_limit_trusted_proxies.clone(),
+            // Unix socket peers are the reverse proxy in front of us.
+            trust_unaddressed_peers: config.server.uds.is_some(),
+            ..ClientKeys::default()
+        }
+        .with_api_keys(
+            config.graphql.rate_limit_key_header.clone(),
+            &config.graphql.rate_limit_api_keys,
+        );
+        let limiter = RateLimiter::new(rps, config.graphql.rate_limit_burst).keyed_by(keys);
+        app = app.layer(Extension(limiter));
+    }
+    if let Some(max) = config.graphql.max_concurrency {
+        app = app.layer(Extension(ConcurrencyLimit::new(max)));
+    }
+    if config.graphql.response_cache {
+        app = app.layer(Extension(ResponseCache::new(config.graphql.response_cache_ttl)));
+    }
+    if let Some(secret) = &config.auth.jwt_secret {
+        app = app.layer(Extension(JwtKeys::new(secret.as_bytes())));
+    }
+    if config.auth.required {
+        app = app.layer(Extension(AuthRequired));
+    }
+    if let Some(credentials) = &config.graphql.ide_credentials {
+        app = app.layer(Extension(IdeAuth::new(credentials)));
+    }
+    if let Some(token) = &config.metrics.auth_token {
+        app = app.layer(Extension(MetricsToken::new(token)));
+    }
+    if !config.metrics.allowed_cidrs.is_empty() {
+        app = app.layer(Extension(MetricsAllowList::new(&config.metrics.allowed_cidrs)));
+    }
+
+    app
+        // Outside `track_metrics`, so it records the handler's own status and
+        // latency, and streamed bodies are encoded chunk by chunk.
+        .layer(create_compression_layer(config.graphql.compression_min_bytes))
+        .layer(create_cors_layer(&config.cors))
+        .layer(Extension(UploadLimits {
+            max_file_size: config.graphql.max_upload_size,
+            max_files: config.graphql.max_upload_files,
+            max_request_size: config.graphql.max_upload_request_size,
+        }))
+        .layer(Extension(BatchLimit(config.graphql.max_batch_size)))
+        .layer(Extension(BodyLimit(config.graphql.max_request_bytes)))
+        .layer(Extension(ExecutionTimeout(config.graphql.request_timeout)))
+        .layer(Extension(WsLimits::new(
+            config.graphql.ws_max_connections,
+            config.graphql.ws_max_subscriptions,
+        )))
+        .layer(Extension(IdeSettings {
+            ide: config.graphql.ide,
+            base_path: config.graphql.base_path.as_str().into(),
+        }))
+        .layer(Extension(config.server.health_format))
+        .layer(Extension(HealthChecks::new(vec![
+            Box::new(SchemaCheck::new(&schema)),
+            Box::new(StoreCheck(store.clone())),
+            Box::new(LifecycleCheck(shutdown.clone())),
+        ])))
         .layer(Extension(schema))
+        .layer(Extension(store))
+        .layer(Extension(shutdown))
+        // Outermost, so every response, including timeouts, carries the id.
+        .layer(middleware::from_fn(request_id))
 }
 
 async fn shutdown_signal() {
@@ -80,26 +465,121 @@ async fn shutdown_signal() {
         _ = ctrl_c => {},
         _ = terminate => {},
     }
-    opentelemetry::global::shutdown_tracer_provider();
+    info!("Shutdown signal received");
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::routes::Health;
+    use crate::config::{
+        AuthConfig, CorsConfig, GraphQLConfig, GraphQLIde, HealthFormat, IdeCredentials,
+        MetricsConfig,
+    };
+    use crate::routes::health::{
+        ComponentHealth, ComponentStatus, FailedCheck, Health, HealthCheck, Readiness,
+    };
     use axum::{
         body::Body,
+        extract::ConnectInfo,
         http::{
+            header,
             Method,
             Request,
+            StatusCode,
         },
-        response::Response
+        response::Response,
     };
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+    use std::net::{SocketAddr, TcpListener};
+    use tokio_tungstenite::tungstenite::{
+        client::IntoClientRequest,
+        http::HeaderValue,
+        protocol::frame::coding::CloseCode,
+        Message,
+    };
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
     use tower::ServiceExt;
 
-    fn test_create_app() -> Router {
+    fn test_create_app(checks: Vec<Box<dyn HealthCheck>>) -> Router {
         Router::new()
             .route("/health", get(health))
+            .layer(Extension(HealthChecks::new(checks)))
+    }
+
+    struct FailingCheck;
+
+    #[async_trait::async_trait]
+    impl HealthCheck for FailingCheck {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            Err("unavailable".into())
+        }
+    }
+
+    struct OptionalCheck;
+
+    #[async_trait::async_trait]
+    impl HealthCheck for OptionalCheck {
+        fn name(&self) -> &'static str {
+            "optional"
+        }
+
+        fn required(&self) -> bool {
+            false
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            Err("not configured".into())
+        }
+    }
+
+    fn test_create_readiness_app(checks: Vec<Box<dyn HealthCheck>>) -> Router {
+        Router::new()
+            .route("/health/ready", get(readiness))
+            .layer(Extension(HealthChecks::new(checks)))
+    }
+
+    fn test_schema() -> ServiceSchema {
+        create_schema(&GraphQLConfig::default(), NoteStore::default())
+    }
+
+    fn test_create_graphql_app(config: GraphQLConfig) -> Router {
+        let store = NoteStore::default();
+        Router::new()
+            .route("/", post(graphql_handler))
+            .layer(Extension(create_schema(&config, store.clone())))
+            .layer(Extension(store))
+            .layer(Extension(BatchLimit(config.max_batch_size)))
+            .layer(Extension(BodyLimit(config.max_request_bytes)))
+            .layer(Extension(ExecutionTimeout(config.request_timeout)))
+            .layer(Extension(UploadLimits {
+                max_file_size: config.max_upload_size,
+                max_files: config.max_upload_files,
+                max_request_size: config.max_upload_request_size,
+            }))
+            .layer(Extension(JwtKeys::new(TEST_JWT_SECRET)))
+    }
+
+    fn test_create_full_app(config: GraphQLConfig) -> Router {
+        let shutdown = Shutdown::new();
+        let app = test_create_full_app_with_shutdown(config, &shutdown);
+        shutdown.mark_ready();
+        app
+    }
+
+    fn test_create_full_app_with_shutdown(graphql: GraphQLConfig, shutdown: &Shutdown) -> Router {
+        test_create_full_app_with(Config { graphql, ..Config::default() }, shutdown)
+    }
+
+    fn test_create_full_app_with(config: Config, shutdown: &Shutdown) -> Router {
+        let store = NoteStore::default();
+        let schema = create_schema(&config.graphql, store.clone());
+        create_app(&config, schema, store, shutdown.signal())
     }
 
     fn get_req_with_empty(method: Method, path: &str) -> Request<Body> {
@@ -110,6 +590,29 @@ mod test {
             .unwrap()
     }
 
+    fn post_req_with_graphql(query: &str) -> Request<Body> {
+        post_req_with_json(json!({ "query": query }))
+    }
+
+    fn post_req_with_json(body: Value) -> Request<Body> {
+        Request::builder()
+            .uri("/")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn res_json(res: Response) -> Value {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    async fn res_text(res: Response) -> String {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
     async fn res_health(res: Response) -> Health {
         let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
         let body: String = String::from_utf8(bytes.to_vec()).unwrap();
@@ -121,13 +624,2777 @@ mod test {
     #[tokio::test]
     async fn health_check() {
         let expected = Health {
-            healthy: true
+            healthy: true,
+            reason: None,
+            components: [(
+                "schema".to_owned(),
+                ComponentHealth { status: ComponentStatus::Up, message: None },
+            )]
+            .into_iter()
+            .collect(),
         };
 
+        let checks: Vec<Box<dyn HealthCheck>> = vec![Box::new(SchemaCheck::new(&test_schema()))];
         let req = get_req_with_empty(Method::GET, "/health");
-        let res = test_create_app().oneshot(req).await.unwrap();
+        let res = test_create_app(checks).oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
         let health = res_health(res).await;
 
         assert_eq!(expected, health);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn health_fails_with_a_required_component_down() {
+        let checks: Vec<Box<dyn HealthCheck>> = vec![Box::new(FailingCheck), Box::new(OptionalCheck)];
+        let req = get_req_with_empty(Method::GET, "/health");
+        let res = test_create_app(checks).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let health = res_health(res).await;
+        assert!(!health.healthy);
+        assert_eq!(
+            health.components["failing"],
+            ComponentHealth { status: ComponentStatus::Down, message: Some("unavailable".into()) }
+        );
+        assert_eq!(health.components["optional"].status, ComponentStatus::Down);
+    }
+
+    #[tokio::test]
+    async fn health_can_use_the_rfc_format() {
+        let checks: Vec<Box<dyn HealthCheck>> =
+            vec![Box::new(SchemaCheck::new(&test_schema())), Box::new(FailingCheck)];
+        let app = test_create_app(checks).layer(Extension(HealthFormat::Rfc));
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/health")).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/health+json");
+        assert_eq!(
+            res_json(res).await,
+            json!({
+                "status": "fail",
+                "checks": {
+                    "failing": [{ "status": "fail", "output": "unavailable" }],
+                    "schema": [{ "status": "pass" }],
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn health_keeps_the_native_format_by_default() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/health")).await.unwrap();
+
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+        let body = res_json(res).await;
+        assert_eq!(body["healthy"], true);
+        assert!(body.get("status").is_none());
+    }
+
+    #[tokio::test]
+    async fn health_ignores_optional_components() {
+        let req = get_req_with_empty(Method::GET, "/health");
+        let res = test_create_app(vec![Box::new(OptionalCheck)]).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res_health(res).await.healthy);
+    }
+
+    #[tokio::test]
+    async fn readiness_without_checks_is_ready() {
+        let req = get_req_with_empty(Method::GET, "/health/ready");
+        let res = test_create_readiness_app(Vec::new()).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let readiness: Readiness = serde_json::from_value(res_json(res).await).unwrap();
+        assert_eq!(
+            readiness,
+            Readiness { ready: true, checks: BTreeMap::new(), failing: Vec::new() }
+        );
+    }
+
+    #[tokio::test]
+    async fn readiness_lists_every_check() {
+        let checks: Vec<Box<dyn HealthCheck>> =
+            vec![Box::new(SchemaCheck::new(&test_schema())), Box::new(FailingCheck)];
+        let req = get_req_with_empty(Method::GET, "/health/ready");
+        let res = test_create_readiness_app(checks).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let readiness: Readiness = serde_json::from_value(res_json(res).await).unwrap();
+        assert!(!readiness.ready);
+        assert_eq!(
+            readiness.checks,
+            BTreeMap::from([
+                ("failing".to_owned(), ComponentStatus::Down),
+                ("schema".to_owned(), ComponentStatus::Up),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn readiness_pings_the_store() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/readyz")).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let readiness: Readiness = serde_json::from_value(res_json(res).await).unwrap();
+        assert_eq!(readiness.checks["store"], ComponentStatus::Up);
+    }
+
+    #[tokio::test]
+    async fn readiness_lists_failing_checks() {
+        let req = get_req_with_empty(Method::GET, "/health/ready");
+        let res = test_create_readiness_app(vec![Box::new(FailingCheck)])
+            .oneshot(req)
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let readiness: Readiness = serde_json::from_value(res_json(res).await).unwrap();
+        assert_eq!(
+            readiness.failing,
+            vec![FailedCheck { name: "failing".into(), error: "unavailable".into() }]
+        );
+    }
+
+    #[tokio::test]
+    async fn readiness_follows_the_server_lifecycle() {
+        let shutdown = Shutdown::new();
+        let app = test_create_full_app_with_shutdown(GraphQLConfig::default(), &shutdown);
+        let status = |path: &'static str| {
+            let app = app.clone();
+            async move {
+                app.oneshot(get_req_with_empty(Method::GET, path))
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+
+        assert_eq!(status("/readyz").await, StatusCode::SERVICE_UNAVAILABLE);
+        shutdown.mark_ready();
+        assert_eq!(status("/readyz").await, StatusCode::OK);
+        assert_eq!(status("/health").await, StatusCode::OK);
+
+        shutdown.trigger();
+        let res = app.clone().oneshot(get_req_with_empty(Method::GET, "/readyz")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let readiness: Readiness = serde_json::from_value(res_json(res).await).unwrap();
+        assert_eq!(
+            readiness.failing,
+            vec![FailedCheck { name: "lifecycle".into(), error: "shutting down".into() }]
+        );
+        assert_eq!(status("/health").await, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status("/livez").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn info_is_an_alias_for_version() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/info")).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res_json(res).await;
+        assert!(!body["version"].as_str().unwrap().is_empty());
+        assert!(!body["commit"].as_str().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn version_reports_the_build() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.clone().oneshot(get_req_with_empty(Method::GET, "/version")).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res_json(res).await;
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        for key in ["commit", "build_timestamp", "rustc"] {
+            assert!(body[key].is_string(), "missing {}", key);
+        }
+
+        let data = graphql_data(&app, "{ version { version commit buildTimestamp rustc } }").await;
+        assert_eq!(data["version"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(data["version"]["commit"], body["commit"]);
+    }
+
+    #[tokio::test]
+    async fn create_note_then_query() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let req = post_req_with_graphql(
+            r#"mutation { createNote(title: "First", body: "Hello") { id title body } }"#,
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        let created = res_json(res).await;
+        let id = created["data"]["createNote"]["id"].as_str().unwrap().to_owned();
+        assert_eq!(created["data"]["createNote"]["title"], "First");
+        assert!(created["extensions"]["traceId"].is_string());
+
+        let query = format!(
+            r#"{{ note(id: "{}") {{ title body }} notes {{ edges {{ node {{ id }} }} }} }}"#,
+            id
+        );
+        let res = app.oneshot(post_req_with_graphql(&query)).await.unwrap();
+        let body = res_json(res).await;
+
+        assert_eq!(body["data"]["note"], json!({ "title": "First", "body": "Hello" }));
+        assert_eq!(body["data"]["notes"]["edges"], json!([{ "node": { "id": id } }]));
+    }
+
+    #[tokio::test]
+    async fn create_note_rejects_empty_title() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let req = post_req_with_graphql(r#"mutation { createNote(title: " ", body: "") { id } }"#);
+        let res = app.oneshot(req).await.unwrap();
+        let body = res_json(res).await;
+
+        assert_eq!(body["errors"][0]["message"], "title must not be empty");
+    }
+
+    #[tokio::test]
+    async fn update_and_delete_note_mutations() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let req = post_req_with_graphql(r#"mutation { createNote(title: "Draft", body: "") { id } }"#);
+        let created = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        let id = created["data"]["createNote"]["id"].as_str().unwrap().to_owned();
+
+        let mutation = format!(
+            r#"mutation {{ updateNote(id: "{}", body: "Done") {{ id title body }} }}"#,
+            id
+        );
+        let updated = res_json(app.clone().oneshot(post_req_with_graphql(&mutation)).await.unwrap()).await;
+        assert_eq!(
+            updated["data"]["updateNote"],
+            json!({ "id": id, "title": "Draft", "body": "Done" })
+        );
+
+        let mutation = format!(r#"mutation {{ deleteNote(id: "{}") {{ id }} }}"#, id);
+        let admin = token_with_roles(3600, &["admin"]);
+        let req = authorized(post_req_with_graphql(&mutation), &admin);
+        let deleted = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        assert_eq!(deleted["data"]["deleteNote"]["id"], id.as_str());
+
+        let req = authorized(post_req_with_graphql(&mutation), &admin);
+        let deleted_again = res_json(app.oneshot(req).await.unwrap()).await;
+        assert_eq!(
+            deleted_again["errors"][0]["message"],
+            format!("note '{}' not found", id)
+        );
+    }
+
+    #[tokio::test]
+    async fn query_over_max_depth_is_rejected() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            max_depth: Some(1),
+            ..GraphQLConfig::default()
+        });
+
+        let req = post_req_with_graphql("{ notes { edges { node { id } } } }");
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res_json(res).await;
+        assert_eq!(body["errors"][0]["message"], "Query is nested too deep.");
+        assert_eq!(body["errors"][0]["extensions"]["code"], "QUERY_TOO_DEEP");
+        assert!(body["data"].is_null());
+    }
+
+    #[tokio::test]
+    async fn unlimited_depth_accepts_nested_query() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            max_depth: None,
+            ..GraphQLConfig::default()
+        });
+
+        let req = post_req_with_graphql("{ notes { edges { node { id } } } }");
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        assert!(body.get("errors").is_none());
+        assert_eq!(body["data"]["notes"]["edges"], json!([]));
+    }
+
+    fn test_create_complexity_app(max_complexity: usize) -> Router {
+        test_create_graphql_app(GraphQLConfig {
+            max_complexity: Some(max_complexity),
+            ..GraphQLConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn query_below_max_complexity_is_executed() {
+        let req = post_req_with_graphql("{ notes(first: 4) { edges { node { id title } } } }");
+        let res = test_create_complexity_app(20).oneshot(req).await.unwrap();
+        let body = res_json(res).await;
+
+        assert!(body.get("errors").is_none());
+        assert_eq!(body["data"]["notes"]["edges"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn query_above_max_complexity_is_rejected() {
+        let req = post_req_with_graphql("{ notes(first: 6) { edges { node { id title } } } }");
+        let res = test_create_complexity_app(20).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res_json(res).await;
+        assert_eq!(body["errors"][0]["message"], "Query is too complex.");
+        assert_eq!(body["errors"][0]["extensions"]["code"], "QUERY_TOO_COMPLEX");
+    }
+
+    fn spawn_server(app: Router) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::from_tcp(listener).unwrap().serve(app.into_make_service());
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn bind_reports_the_ephemeral_port() {
+        let (listener, addr) = bind(&ServerConfig {
+            host: "127.0.0.1".parse().unwrap(),
+            port: 0,
+            ..ServerConfig::default()
+        });
+        assert_ne!(addr.port(), 0);
+        assert_eq!(listener.local_addr().unwrap(), addr);
+
+        let app = Router::new().route("/health/live", get(liveness));
+        let server = Server::from_tcp(listener).unwrap().serve(app.into_make_service());
+        tokio::spawn(server);
+        let uri = format!("http://{}/health/live", addr).parse().unwrap();
+        let res = hyper::Client::new().get(uri).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    async fn next_ws_json<S>(stream: &mut S) -> Value
+    where
+        S: futures_util::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        match stream.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_drops_requests_after_the_drain_timeout() {
+        let app = Router::new().route(
+            "/slow",
+            get(|| async { tokio::time::sleep(Duration::from_secs(60)).await }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (trigger, signal) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            Listener::Plain(listener),
+            app,
+            Shutdown::new(),
+            Duration::ZERO,
+            Duration::from_millis(100),
+            async move {
+                let _ = signal.await;
+            },
+        ));
+        let uri = format!("http://{}/slow", addr).parse().unwrap();
+        let request = tokio::spawn(hyper::Client::new().get(uri));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let started = std::time::Instant::now();
+        trigger.send(()).unwrap();
+        let cut_off = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("shutdown should not wait for the slow request")
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(cut_off, 1);
+        assert!(request.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn lame_duck_keeps_accepting_requests_before_the_drain() {
+        let shutdown = Shutdown::new();
+        let app = test_create_full_app_with_shutdown(GraphQLConfig::default(), &shutdown);
+        shutdown.mark_ready();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (trigger, signal) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            Listener::Plain(listener),
+            app,
+            shutdown,
+            Duration::from_millis(300),
+            Duration::from_secs(5),
+            async move {
+                let _ = signal.await;
+            },
+        ));
+        trigger.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = hyper::Client::new();
+        let uri = format!("http://{}/health", addr).parse().unwrap();
+        let res = client.get(uri).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value =
+            serde_json::from_slice(&hyper::body::to_bytes(res.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["healthy"], false);
+        assert_eq!(body["reason"], "shutting_down");
+        let uri = format!("http://{}/?query=%7B%20hello%20%7D", addr).parse().unwrap();
+        let res = client.get(uri).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        drop(client);
+        let cut_off = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("the drain should start after the lame duck period")
+            .unwrap();
+        assert_eq!(cut_off, 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_over_a_unix_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("axum-graphql-{}.sock", std::process::id()));
+        // A socket file left behind by a previous run.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let unix = bind_unix(&UdsConfig {
+            path: path.clone(),
+            mode: 0o600,
+        });
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let (trigger, signal) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            Listener::Unix(unix, SocketFile(path.clone())),
+            test_create_full_app(GraphQLConfig::default()),
+            Shutdown::new(),
+            Duration::ZERO,
+            Duration::from_secs(5),
+            async move {
+                let _ = signal.await;
+            },
+        ));
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+        tokio::spawn(connection);
+        let req = Request::get("/health")
+            .header(header::HOST, "localhost")
+            .body(Body::empty())
+            .unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        drop(sender);
+        trigger.send(()).unwrap();
+        server.await.unwrap();
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket_clients_are_rate_limited_by_forwarded_address() {
+        let path = std::env::temp_dir().join(format!(
+            "axum-graphql-{}-rate-limit.sock",
+            std::process::id()
+        ));
+        let uds = UdsConfig {
+            path: path.clone(),
+            mode: 0o600,
+        };
+        let unix = bind_unix(&uds);
+        let config = Config {
+            server: ServerConfig {
+                uds: Some(uds),
+                ..ServerConfig::default()
+            },
+            graphql: GraphQLConfig {
+                rate_limit_rps: Some(1),
+                rate_limit_burst: 1,
+                ..GraphQLConfig::default()
+            },
+            ..Config::default()
+        };
+        let shutdown = Shutdown::new();
+        let app = test_create_full_app_with(config, &shutdown);
+        shutdown.mark_ready();
+
+        let (trigger, signal) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            Listener::Unix(unix, SocketFile(path.clone())),
+            app,
+            Shutdown::new(),
+            Duration::ZERO,
+            Duration::from_secs(5),
+            async move {
+                let _ = signal.await;
+            },
+        ));
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+        tokio::spawn(connection);
+        let mut status_for = |client: &'static str| {
+            let req = Request::post("/")
+                .header(header::HOST, "localhost")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header("x-forwarded-for", client)
+                .body(Body::from(json!({ "query": "{ hello }" }).to_string()))
+                .unwrap();
+            let res = sender.send_request(req);
+            async move { res.await.unwrap().status() }
+        };
+
+        assert_eq!(status_for("203.0.113.1").await, StatusCode::OK);
+        assert_eq!(status_for("203.0.113.2").await, StatusCode::OK);
+        assert_eq!(status_for("203.0.113.1").await, StatusCode::TOO_MANY_REQUESTS);
+
+        drop(sender);
+        trigger.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn subscription_over_websocket() {
+        let shutdown = Shutdown::new();
+        let app = Router::new()
+            .route("/ws", get(graphql_subscription))
+            .layer(Extension(test_schema()))
+            .layer(Extension(shutdown.signal()));
+        let addr = spawn_server(app);
+
+        let mut req = format!("ws://{}/ws", addr).into_client_request().unwrap();
+        req.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_static("graphql-transport-ws"),
+        );
+        let (mut socket, _) = tokio_tungstenite::connect_async(req).await.unwrap();
+
+        socket
+            .send(Message::Text(json!({ "type": "connection_init" }).to_string()))
+            .await
+            .unwrap();
+        assert_eq!(next_ws_json(&mut socket).await["type"], "connection_ack");
+
+        let subscribe = json!({
+            "id": "1",
+            "type": "subscribe",
+            "payload": { "query": "subscription { interval(n: 1) }" },
+        });
+        socket.send(Message::Text(subscribe.to_string())).await.unwrap();
+
+        let next = next_ws_json(&mut socket).await;
+        assert_eq!(next["type"], "next");
+        assert_eq!(next["payload"]["data"]["interval"], 1);
+
+        shutdown.trigger();
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                Message::Close(frame) => {
+                    assert_eq!(frame.unwrap().code, CloseCode::Away);
+                    break;
+                }
+                Message::Text(_) => continue,
+                other => panic!("unexpected message: {:?}", other),
+            }
+        }
+    }
+
+    async fn connect_ws(addr: SocketAddr) -> WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>> {
+        let mut req = format!("ws://{}/ws", addr).into_client_request().unwrap();
+        req.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_static("graphql-transport-ws"),
+        );
+        tokio_tungstenite::connect_async(req).await.unwrap().0
+    }
+
+    async fn next_ws_close<S>(stream: &mut S) -> CloseCode
+    where
+        S: futures_util::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        loop {
+            match stream.next().await.unwrap().unwrap() {
+                Message::Close(frame) => return frame.unwrap().code,
+                Message::Text(_) => continue,
+                other => panic!("unexpected message: {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn websocket_connections_and_operations_are_capped() {
+        let shutdown = Shutdown::new();
+        let app = Router::new()
+            .route("/ws", get(graphql_subscription))
+            .layer(Extension(WsLimits::new(Some(1), Some(1))))
+            .layer(Extension(test_schema()))
+            .layer(Extension(shutdown.signal()));
+        let addr = spawn_server(app);
+
+        let mut socket = connect_ws(addr).await;
+        socket
+            .send(Message::Text(json!({ "type": "connection_init" }).to_string()))
+            .await
+            .unwrap();
+        assert_eq!(next_ws_json(&mut socket).await["type"], "connection_ack");
+
+        let mut rejected = connect_ws(addr).await;
+        assert_eq!(next_ws_close(&mut rejected).await, CloseCode::Again);
+
+        for id in ["1", "2"] {
+            let subscribe = json!({
+                "id": id,
+                "type": "subscribe",
+                "payload": { "query": "subscription { interval(n: 1) }" },
+            });
+            socket.send(Message::Text(subscribe.to_string())).await.unwrap();
+        }
+        assert_eq!(next_ws_close(&mut socket).await, CloseCode::Policy);
+    }
+
+    #[tokio::test]
+    async fn introspection_is_enabled_by_default() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let req = post_req_with_graphql("{ __schema { queryType { name } } }");
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        assert_eq!(body["data"]["__schema"]["queryType"]["name"], "QueryRoot");
+    }
+
+    #[tokio::test]
+    async fn introspection_can_be_disabled() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            disable_introspection: true,
+            ..GraphQLConfig::default()
+        });
+
+        let req = post_req_with_graphql("{ __schema { queryType { name } } }");
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        let message = body["errors"][0]["message"].as_str().unwrap();
+        assert!(message.starts_with(r#"Unknown field "__schema""#), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn schema_sdl_is_served_as_text() {
+        let req = get_req_with_empty(Method::GET, "/schema");
+        let res = test_create_full_app(GraphQLConfig::default()).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain"));
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let sdl = String::from_utf8(bytes.to_vec()).unwrap();
+        for root in ["type QueryRoot", "type MutationRoot", "type SubscriptionRoot"] {
+            assert!(sdl.contains(root), "missing {}", root);
+        }
+    }
+
+    /// Any schema change shows up as a diff of `schema.graphql`; run with
+    /// `UPDATE_SCHEMA_SNAPSHOT=1` to accept one.
+    #[test]
+    fn merged_roots_keep_every_field() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/schema.graphql");
+        let sdl = test_schema().sdl();
+        if std::env::var_os("UPDATE_SCHEMA_SNAPSHOT").is_some() {
+            std::fs::write(path, &sdl).unwrap();
+        }
+
+        let snapshot = std::fs::read_to_string(path).unwrap();
+        assert_eq!(sdl, snapshot, "schema.graphql is out of date");
+    }
+
+    #[tokio::test]
+    async fn schema_sdl_is_hidden_without_introspection() {
+        let config = GraphQLConfig {
+            disable_introspection: true,
+            ..GraphQLConfig::default()
+        };
+        let req = get_req_with_empty(Method::GET, "/schema");
+        let res = test_create_full_app(config).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn federation_resolves_entities() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            federation: true,
+            ..GraphQLConfig::default()
+        });
+
+        let req = post_req_with_graphql(r#"mutation { createNote(title: "Shared", body: "") { id } }"#);
+        let created = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        let id = created["data"]["createNote"]["id"].clone();
+
+        let req = post_req_with_json(json!({
+            "query": "query($representations: [_Any!]!) { \
+                _entities(representations: $representations) { ... on Note { id title } } \
+                _service { sdl } }",
+            "variables": { "representations": [{ "__typename": "Note", "id": id }] },
+        }));
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        assert_eq!(body["data"]["_entities"], json!([{ "id": id, "title": "Shared" }]));
+        let sdl = body["data"]["_service"]["sdl"].as_str().unwrap();
+        assert!(sdl.contains(r#"@key(fields: "id")"#), "{}", sdl);
+    }
+
+    async fn create_notes(app: &Router, count: usize) {
+        for n in 0..count {
+            let mutation = format!(r#"mutation {{ createNote(title: "Note {}", body: "") {{ id }} }}"#, n);
+            app.clone().oneshot(post_req_with_graphql(&mutation)).await.unwrap();
+        }
+    }
+
+    async fn notes_page(app: &Router, args: &str) -> Value {
+        let query = format!(
+            "{{ notes({}) {{ edges {{ cursor node {{ title }} }} pageInfo {{ hasPreviousPage hasNextPage endCursor }} }} }}",
+            args
+        );
+        res_json(app.clone().oneshot(post_req_with_graphql(&query)).await.unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn notes_connection_on_empty_store() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let body = notes_page(&app, "first: 2").await;
+
+        assert_eq!(body["data"]["notes"]["edges"], json!([]));
+        assert_eq!(body["data"]["notes"]["pageInfo"]["hasPreviousPage"], false);
+        assert_eq!(body["data"]["notes"]["pageInfo"]["hasNextPage"], false);
+    }
+
+    #[tokio::test]
+    async fn notes_connection_pages_forward() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        create_notes(&app, 3).await;
+
+        let first = notes_page(&app, "first: 2").await;
+        let page = &first["data"]["notes"];
+        assert_eq!(page["edges"].as_array().unwrap().len(), 2);
+        assert_eq!(page["pageInfo"]["hasPreviousPage"], false);
+        assert_eq!(page["pageInfo"]["hasNextPage"], true);
+
+        let args = format!(r#"first: 2, after: {}"#, page["pageInfo"]["endCursor"]);
+        let second = notes_page(&app, &args).await;
+        let page = &second["data"]["notes"];
+        assert_eq!(page["edges"], json!([{ "cursor": page["pageInfo"]["endCursor"], "node": { "title": "Note 2" } }]));
+        assert_eq!(page["pageInfo"]["hasPreviousPage"], true);
+        assert_eq!(page["pageInfo"]["hasNextPage"], false);
+    }
+
+    #[tokio::test]
+    async fn notes_connection_pages_backward() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        create_notes(&app, 3).await;
+
+        let body = notes_page(&app, "last: 2").await;
+        let page = &body["data"]["notes"];
+
+        let titles: Vec<&Value> = page["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|edge| &edge["node"]["title"])
+            .collect();
+        assert_eq!(titles, vec!["Note 1", "Note 2"]);
+        assert_eq!(page["pageInfo"]["hasPreviousPage"], true);
+        assert_eq!(page["pageInfo"]["hasNextPage"], false);
+    }
+
+    #[tokio::test]
+    async fn notes_connection_after_past_the_end() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        create_notes(&app, 2).await;
+
+        let body = notes_page(&app, r#"first: 2, after: "MTA=""#).await;
+        let page = &body["data"]["notes"];
+
+        assert_eq!(page["edges"], json!([]));
+        assert_eq!(page["pageInfo"]["hasPreviousPage"], true);
+        assert_eq!(page["pageInfo"]["hasNextPage"], false);
+    }
+
+    #[tokio::test]
+    async fn notes_connection_after_the_largest_cursor() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        create_notes(&app, 2).await;
+
+        let after = base64::encode(usize::MAX.to_string());
+        let body = notes_page(&app, &format!(r#"first: 2, after: "{}""#, after)).await;
+        let page = &body["data"]["notes"];
+
+        assert_eq!(body["errors"], Value::Null);
+        assert_eq!(page["edges"], json!([]));
+        assert_eq!(page["pageInfo"]["hasNextPage"], false);
+    }
+
+    #[tokio::test]
+    async fn notes_connection_rejects_first_and_last() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let body = notes_page(&app, "first: 1, last: 1").await;
+
+        let message = body["errors"][0]["message"].as_str().unwrap();
+        assert!(message.contains("first") && message.contains("last"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn notes_resolve_their_author() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let req = post_req_with_graphql(r#"mutation { createAuthor(name: "Ada") { id } }"#);
+        let created = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        let author_id = created["data"]["createAuthor"]["id"].as_str().unwrap().to_owned();
+        for title in ["One", "Two"] {
+            let mutation = format!(
+                r#"mutation {{ createNote(title: "{}", body: "", authorId: "{}") {{ id }} }}"#,
+                title, author_id
+            );
+            app.clone().oneshot(post_req_with_graphql(&mutation)).await.unwrap();
+        }
+
+        let req = post_req_with_graphql("{ notes { edges { node { author { id name } } } } }");
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        let author = json!({ "id": author_id, "name": "Ada" });
+        assert_eq!(
+            body["data"]["notes"]["edges"],
+            json!([{ "node": { "author": author } }, { "node": { "author": author } }])
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_operations_time_out_with_a_graphql_error() {
+        // Author lookups wait out the loader delay, well past the timeout.
+        let app = test_create_graphql_app(GraphQLConfig {
+            loader_delay: Duration::from_secs(5),
+            request_timeout: Duration::from_millis(50),
+            ..GraphQLConfig::default()
+        });
+        let req = post_req_with_graphql(
+            r#"mutation {
+                createAuthor(name: "Ada") { id }
+                createNote(title: "Slow", body: "", authorId: "1") { id }
+            }"#,
+        );
+        app.clone().oneshot(req).await.unwrap();
+
+        let req = post_req_with_graphql("{ notes { edges { node { author { name } } } } }");
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res_json(res).await;
+        assert_eq!(body["errors"][0]["extensions"]["code"], "TIMEOUT");
+        assert!(body["extensions"]["traceId"].is_string());
+    }
+
+    #[tokio::test]
+    async fn cors_preflight_is_answered() {
+        let req = Request::builder()
+            .uri("/")
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, "http://example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+        let res = test_create_full_app(GraphQLConfig::default()).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[tokio::test]
+    async fn cors_allows_only_listed_origins() {
+        let config = CorsConfig {
+            allowed_origins: Some(vec![HeaderValue::from_static("https://app.example.com")]),
+            allow_credentials: true,
+            ..CorsConfig::default()
+        };
+        let app = Router::new()
+            .route("/", post(|| async { "ok" }))
+            .layer(create_cors_layer(&config));
+        let post_from = |origin: &'static str| {
+            Request::builder()
+                .uri("/")
+                .method(Method::POST)
+                .header(header::ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let res = app.clone().oneshot(post_from("https://app.example.com")).await.unwrap();
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+
+        let res = app.oneshot(post_from("https://evil.example.com")).await.unwrap();
+        assert!(!res.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn multipart_upload_reaches_the_resolver() {
+        let operations = json!({
+            "query": "mutation($file: Upload!) { uploadFile(file: $file) { filename contentType size sha256 } }",
+            "variables": { "file": null },
+        });
+        let body = format!(
+            "--boundary\r\n\
+             Content-Disposition: form-data; name=\"operations\"\r\n\r\n\
+             {}\r\n\
+             --boundary\r\n\
+             Content-Disposition: form-data; name=\"map\"\r\n\r\n\
+             {{\"0\": [\"variables.file\"]}}\r\n\
+             --boundary\r\n\
+             Content-Disposition: form-data; name=\"0\"; filename=\"hello.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n\
+             hello world\r\n\
+             --boundary--\r\n",
+            operations
+        );
+        let req = Request::builder()
+            .uri("/")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=boundary")
+            .body(Body::from(body))
+            .unwrap();
+
+        let res = test_create_graphql_app(GraphQLConfig::default()).oneshot(req).await.unwrap();
+        let body = res_json(res).await;
+
+        assert_eq!(
+            body["data"]["uploadFile"],
+            json!({
+                "filename": "hello.txt",
+                "contentType": "text/plain",
+                "size": 11,
+                "sha256": "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            })
+        );
+    }
+
+    async fn metrics_status(auth_token: Option<&str>, authorization: Option<&str>) -> StatusCode {
+        let metrics = MetricsConfig {
+            auth_token: auth_token.map(String::from),
+            ..MetricsConfig::default()
+        };
+        let config = Config { metrics, ..Config::default() };
+        let app = test_create_full_app_with(config, &Shutdown::new());
+        let mut req = Request::builder().uri("/metrics");
+        if let Some(authorization) = authorization {
+            req = req.header(header::AUTHORIZATION, authorization);
+        }
+        let res = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+        res.status()
+    }
+
+    #[tokio::test]
+    async fn metrics_require_the_token_when_configured() {
+        let token = Some("s3cret");
+        assert_eq!(metrics_status(token, Some("Bearer s3cret")).await, StatusCode::OK);
+        let basic = format!("Basic {}", base64::encode("prometheus:s3cret"));
+        assert_eq!(metrics_status(token, Some(&basic)).await, StatusCode::OK);
+
+        assert_eq!(metrics_status(token, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(metrics_status(token, Some("Bearer guess")).await, StatusCode::UNAUTHORIZED);
+        let wrong = format!("Basic {}", base64::encode("prometheus:guess"));
+        assert_eq!(metrics_status(token, Some(&wrong)).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn metrics_are_open_without_a_token() {
+        assert_eq!(metrics_status(None, None).await, StatusCode::OK);
+        assert_eq!(metrics_status(None, Some("Bearer anything")).await, StatusCode::OK);
+    }
+
+    async fn metrics_status_from(peer: [u8; 4], authorization: Option<&str>) -> StatusCode {
+        let metrics = MetricsConfig {
+            auth_token: Some("s3cret".into()),
+            allowed_cidrs: vec!["10.0.0.0/8".parse().unwrap()],
+            ..MetricsConfig::default()
+        };
+        let config = Config { metrics, ..Config::default() };
+        let app = test_create_full_app_with(config, &Shutdown::new());
+        let mut req = get_req_with_empty(Method::GET, "/metrics");
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((peer, 40000))));
+        if let Some(authorization) = authorization {
+            req.headers_mut()
+                .insert(header::AUTHORIZATION, authorization.parse().unwrap());
+        }
+        app.oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn metrics_are_limited_to_allowed_networks() {
+        let token = Some("Bearer s3cret");
+        assert_eq!(metrics_status_from([10, 1, 2, 3], token).await, StatusCode::OK);
+        assert_eq!(metrics_status_from([10, 1, 2, 3], None).await, StatusCode::UNAUTHORIZED);
+
+        let res_status = metrics_status_from([203, 0, 113, 5], token).await;
+        assert_eq!(res_status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn metrics_are_never_compressed() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        // Make sure the recorder has something to render.
+        app.clone()
+            .oneshot(get_req_with_empty(Method::GET, "/health"))
+            .await
+            .unwrap();
+
+        let req = Request::builder()
+            .uri("/metrics")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+    }
+
+    fn gzip_req(query: &str) -> Request<Body> {
+        let mut req = post_req_with_graphql(query);
+        req.headers_mut()
+            .insert(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        req
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_gzipped() {
+        use std::io::Read;
+
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app
+            .oneshot(gzip_req("{ __schema { types { name fields { name } } } }"))
+            .await
+            .unwrap();
+
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(&bytes[..])
+            .read_to_string(&mut json)
+            .unwrap();
+        let body: Value = serde_json::from_str(&json).unwrap();
+        assert!(body["data"]["__schema"]["types"].is_array());
+    }
+
+    #[tokio::test]
+    async fn small_responses_are_not_compressed() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.oneshot(gzip_req("{ hello }")).await.unwrap();
+
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(res_json(res).await["data"]["hello"], "Hello World");
+    }
+
+    fn apq_req(query: Option<&str>, hash: &str) -> Request<Body> {
+        let mut body = json!({
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": hash } },
+        });
+        if let Some(query) = query {
+            body["query"] = json!(query);
+        }
+        post_req_with_json(body)
+    }
+
+    #[tokio::test]
+    async fn persisted_query_miss_register_hit() {
+        use sha2::{Digest, Sha256};
+
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let query = "{ hello }";
+        let hash = format!("{:x}", Sha256::digest(query.as_bytes()));
+
+        let miss = res_json(app.clone().oneshot(apq_req(None, &hash)).await.unwrap()).await;
+        assert_eq!(miss["errors"][0]["message"], "PersistedQueryNotFound");
+        assert_eq!(miss["errors"][0]["extensions"]["code"], "PERSISTED_QUERY_NOT_FOUND");
+
+        let register = res_json(app.clone().oneshot(apq_req(Some(query), &hash)).await.unwrap()).await;
+        assert_eq!(register["data"]["hello"], "Hello World");
+
+        let hit = res_json(app.oneshot(apq_req(None, &hash)).await.unwrap()).await;
+        assert_eq!(hit["data"]["hello"], "Hello World");
+    }
+
+    #[tokio::test]
+    async fn persisted_query_with_wrong_hash_is_not_cached() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let hash = "0".repeat(64);
+
+        let register = res_json(app.clone().oneshot(apq_req(Some("{ hello }"), &hash)).await.unwrap()).await;
+        assert_eq!(register["errors"][0]["message"], "provided sha does not match query");
+
+        let lookup = res_json(app.oneshot(apq_req(None, &hash)).await.unwrap()).await;
+        assert_eq!(lookup["errors"][0]["message"], "PersistedQueryNotFound");
+    }
+
+    #[tokio::test]
+    async fn batched_operations_are_answered_in_order() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let req = post_req_with_json(json!([
+            { "query": "{ hello }" },
+            { "query": "{ notes { edges { node { id } } } }" },
+        ]));
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        let responses = body.as_array().expect("batch response is an array");
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["data"]["hello"], "Hello World");
+        assert_eq!(responses[1]["data"]["notes"]["edges"], json!([]));
+        assert_eq!(
+            responses[0]["extensions"]["traceId"],
+            responses[1]["extensions"]["traceId"]
+        );
+    }
+
+    #[tokio::test]
+    async fn single_operation_is_not_wrapped_in_an_array() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let res = app.oneshot(post_req_with_graphql("{ hello }")).await.unwrap();
+        let body = res_json(res).await;
+
+        assert!(body.is_object());
+        assert_eq!(body["data"]["hello"], "Hello World");
+    }
+
+    #[tokio::test]
+    async fn oversized_batch_is_rejected() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            max_batch_size: Some(2),
+            ..GraphQLConfig::default()
+        });
+        let req = post_req_with_json(json!([
+            { "query": "{ hello }" },
+            { "query": "{ hello }" },
+            { "query": "{ hello }" },
+        ]));
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        assert_eq!(
+            body["errors"][0]["message"],
+            "batch of 3 operations exceeds the limit of 2"
+        );
+        assert!(body["data"].is_null());
+    }
+
+    fn test_create_body_limit_app(max_request_bytes: usize) -> Router {
+        test_create_graphql_app(GraphQLConfig {
+            max_request_bytes,
+            ..GraphQLConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn body_over_the_limit_is_rejected() {
+        let mut req = post_req_with_graphql(&format!("{{ hello }} #{}", "x".repeat(64)));
+        let length = hyper::body::HttpBody::size_hint(req.body()).exact().unwrap();
+        req.headers_mut()
+            .insert(header::CONTENT_LENGTH, axum::http::HeaderValue::from(length));
+        let res = test_create_body_limit_app(64).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn chunked_body_over_the_limit_is_rejected() {
+        let body = json!({ "query": format!("{{ hello }} #{}", "x".repeat(64)) }).to_string();
+        let chunks = futures_util::stream::iter(
+            body.into_bytes()
+                .chunks(16)
+                .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()))
+                .collect::<Vec<_>>(),
+        );
+        let req = Request::builder()
+            .uri("/")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::wrap_stream(chunks))
+            .unwrap();
+        let res = test_create_body_limit_app(64).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    fn sized_req(query: &str) -> (Request<Body>, usize) {
+        let mut req = post_req_with_graphql(query);
+        let length = hyper::body::HttpBody::size_hint(req.body()).exact().unwrap() as usize;
+        req.headers_mut()
+            .insert(header::CONTENT_LENGTH, axum::http::HeaderValue::from(length));
+        (req, length)
+    }
+
+    #[tokio::test]
+    async fn body_limit_is_inclusive() {
+        let (req, length) = sized_req("{ hello }");
+        let res = test_create_body_limit_app(length).oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let (req, length) = sized_req("{ hello }");
+        let res = test_create_body_limit_app(length - 1).oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            res_json(res).await["errors"][0]["message"],
+            format!("request body exceeds the limit of {} bytes", length - 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn body_limit_rejections_are_counted() {
+        let app = test_create_full_app(GraphQLConfig {
+            max_request_bytes: 8,
+            ..GraphQLConfig::default()
+        });
+        let (req, _) = sized_req("{ hello }");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let output = create_prometheus_recorder(&MetricsConfig::default().buckets).render();
+        assert!(output.lines().any(|line| line.starts_with("http_requests_total")
+            && line.contains(r#"path="/""#)
+            && line.contains(r#"status="413""#)));
+    }
+
+    #[tokio::test]
+    async fn body_within_the_limit_is_executed() {
+        let res = test_create_body_limit_app(64)
+            .oneshot(post_req_with_graphql("{ hello }"))
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_json(res).await["data"]["hello"], "Hello World");
+    }
+
+    #[tokio::test]
+    async fn slow_request_times_out() {
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                    "done"
+                }),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(handle_timeout_error))
+                    .timeout(std::time::Duration::from_millis(20)),
+            );
+        let req = Request::builder().uri("/slow").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_concurrency_limit_are_shed() {
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    "done"
+                }),
+            )
+            .route_layer(middleware::from_fn(limit_concurrency))
+            .route("/health/live", get(liveness))
+            .layer(Extension(ConcurrencyLimit::new(1)));
+        let recorder = create_prometheus_recorder(&MetricsConfig::default().buckets);
+        let slow = tokio::spawn(app.clone().oneshot(get_req_with_empty(Method::GET, "/slow")));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let started = std::time::Instant::now();
+        let res = app.clone().oneshot(get_req_with_empty(Method::GET, "/slow")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(res_json(res).await["error"], "overloaded");
+        let output = recorder.render();
+        assert!(output.lines().any(|line| line.starts_with("graphql_requests_in_flight ")));
+        assert!(output.lines().any(|line| line.starts_with("graphql_requests_shed_total ")));
+
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/health/live")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(slow.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    fn schedule_reminder_req(id: &str, remind_at: &str) -> Request<Body> {
+        post_req_with_json(json!({
+            "query": "mutation ($input: ReminderInput!) { \
+                scheduleReminder(input: $input) { id noteId remindAt } }",
+            "variables": { "input": { "id": id, "noteId": "1", "remindAt": remind_at } },
+        }))
+    }
+
+    #[tokio::test]
+    async fn reminder_scalars_round_trip() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let req = post_req_with_graphql(
+            r#"mutation { createNote(title: "Dentist", body: "") { createdAt } }"#,
+        );
+        let created = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        let created_at = created["data"]["createNote"]["createdAt"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(created_at).is_ok());
+
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let req = schedule_reminder_req(id, "2030-01-01T09:00:00Z");
+        let scheduled = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        let reminder = &scheduled["data"]["scheduleReminder"];
+        assert_eq!(reminder["id"], id);
+        let remind_at =
+            chrono::DateTime::parse_from_rfc3339(reminder["remindAt"].as_str().unwrap()).unwrap();
+        assert_eq!(remind_at.timestamp(), 1_893_488_400);
+
+        let query = format!(r#"{{ reminder(id: "{}") {{ noteId }} }}"#, id);
+        let fetched = res_json(app.oneshot(post_req_with_graphql(&query)).await.unwrap()).await;
+        assert_eq!(fetched["data"]["reminder"]["noteId"], "1");
+    }
+
+    #[tokio::test]
+    async fn reminders_page_with_opaque_cursors() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        create_notes(&app, 1).await;
+        for (id, remind_at) in [
+            ("67e55044-10b1-426f-9247-bb680e5fe0c8", "2030-01-02T09:00:00Z"),
+            ("9a2b8f5e-3c1d-4e6f-8a7b-0c9d8e7f6a5b", "2030-01-01T09:00:00Z"),
+        ] {
+            app.clone().oneshot(schedule_reminder_req(id, remind_at)).await.unwrap();
+        }
+
+        let query = "{ reminders(first: 1) { edges { cursor node { id } } pageInfo { hasNextPage } } }";
+        let page = &graphql_data(&app, query).await["reminders"];
+        assert_eq!(page["edges"][0]["cursor"], "MA==");
+        assert_eq!(page["edges"][0]["node"]["id"], "9a2b8f5e-3c1d-4e6f-8a7b-0c9d8e7f6a5b");
+        assert_eq!(page["pageInfo"]["hasNextPage"], true);
+
+        let query = r#"{ reminders(first: 1, after: "MA==") { edges { node { id } } } }"#;
+        let page = &graphql_data(&app, query).await["reminders"];
+        assert_eq!(page["edges"][0]["node"]["id"], "67e55044-10b1-426f-9247-bb680e5fe0c8");
+    }
+
+    #[tokio::test]
+    async fn invalid_date_time_is_bad_user_input() {
+        let req = schedule_reminder_req("67e55044-10b1-426f-9247-bb680e5fe0c8", "not-a-date");
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+        let error = &body["errors"][0];
+
+        assert_eq!(error["extensions"]["code"], "BAD_USER_INPUT");
+        assert!(error["message"].as_str().unwrap().contains("DateTime"));
+        assert_eq!(error["path"], json!(["scheduleReminder"]));
+    }
+
+    #[tokio::test]
+    async fn invalid_uuid_is_bad_user_input() {
+        let req = post_req_with_graphql(r#"{ reminder(id: "not-a-uuid") { noteId } }"#);
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+        let error = &body["errors"][0];
+
+        assert_eq!(error["extensions"]["code"], "BAD_USER_INPUT");
+        assert!(error["message"].as_str().unwrap().contains("UUID"));
+        assert_eq!(error["path"], json!(["reminder"]));
+    }
+
+    fn test_create_rate_limited_app(rps: usize) -> Router {
+        Router::new()
+            .route("/", get(health).layer(middleware::from_fn(rate_limit)))
+            .layer(Extension(RateLimiter::new(rps, rps)))
+    }
+
+    fn get_req_from(ip: [u8; 4]) -> Request<Body> {
+        let mut req = get_req_with_empty(Method::GET, "/");
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 40000))));
+        req
+    }
+
+    #[tokio::test]
+    async fn rapid_requests_are_rate_limited() {
+        let app = test_create_rate_limited_app(2);
+        let mut statuses = Vec::new();
+        for _ in 0..5 {
+            let res = app.clone().oneshot(get_req_from([10, 0, 0, 1])).await.unwrap();
+            let status = res.status();
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+                assert_eq!(res_json(res).await["error"], "too_many_requests");
+            }
+            statuses.push(status);
+        }
+
+        assert_eq!(&statuses[..2], &[StatusCode::OK, StatusCode::OK]);
+        assert!(statuses[2..]
+            .iter()
+            .all(|status| *status == StatusCode::TOO_MANY_REQUESTS));
+
+        let other = app.oneshot(get_req_from([10, 0, 0, 2])).await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn trusted_proxies_forward_the_client_address() {
+        let app = test_create_full_app(GraphQLConfig {
+            rate_limit_rps: Some(1),
+            rate_limit_burst: 1,
+            rate_limit_trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+            ..GraphQLConfig::default()
+        });
+        let via_proxy = |client: &'static str| {
+            let mut req = post_req_with_graphql("{ hello }");
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 9], 40000))));
+            req.headers_mut()
+                .insert("x-forwarded-for", HeaderValue::from_static(client));
+            app.clone().oneshot(req)
+        };
+
+        assert_eq!(via_proxy("203.0.113.1").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(via_proxy("203.0.113.2").await.unwrap().status(), StatusCode::OK);
+        let res = via_proxy("203.0.113.1").await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn health_and_metrics_are_not_rate_limited() {
+        let app = test_create_full_app(GraphQLConfig {
+            rate_limit_rps: Some(1),
+            rate_limit_burst: 1,
+            ..GraphQLConfig::default()
+        });
+        let from_client = |mut req: Request<Body>| {
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 9], 40000))));
+            app.clone().oneshot(req)
+        };
+
+        let res = from_client(post_req_with_graphql("{ hello }")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = from_client(post_req_with_graphql("{ hello }")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        for path in ["/health", "/livez", "/readyz", "/metrics"] {
+            let res = from_client(get_req_with_empty(Method::GET, path)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn default_config_does_not_rate_limit() {
+        assert_eq!(GraphQLConfig::default().rate_limit_rps, None);
+        let app = test_create_full_app(GraphQLConfig::default());
+        for _ in 0..150 {
+            let mut req = post_req_with_graphql("{ hello }");
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 9], 40000))));
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolver_errors_carry_a_code() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let req = post_req_with_graphql(r#"mutation { createNote(title: " ", body: "") { id } }"#);
+        let invalid = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        assert_eq!(
+            invalid["errors"][0]["extensions"],
+            json!({ "code": "BAD_USER_INPUT", "field": "title" })
+        );
+
+        let req = authorized(
+            post_req_with_graphql(r#"mutation { deleteNote(id: "42") { id } }"#),
+            &token_with_roles(3600, &["admin"]),
+        );
+        let missing = res_json(app.oneshot(req).await.unwrap()).await;
+        assert_eq!(missing["errors"][0]["message"], "note '42' not found");
+        assert_eq!(
+            missing["errors"][0]["extensions"],
+            json!({ "code": "NOT_FOUND" })
+        );
+    }
+
+    // Executed without the per-request `NoteLoader`, so the resolver fails
+    // with an internal "data does not exist" error.
+    const QUERY_WITHOUT_LOADER: &str = r#"{ note(id: "1") { id } }"#;
+
+    #[tokio::test]
+    async fn internal_errors_are_masked() {
+        let response = test_schema().execute(QUERY_WITHOUT_LOADER).await;
+        let error = serde_json::to_value(&response.errors[0]).unwrap();
+
+        assert_eq!(error["message"], "Internal server error");
+        assert_eq!(error["path"], json!(["note"]));
+        assert_eq!(error["extensions"]["code"], "INTERNAL_SERVER_ERROR");
+        let correlation_id = error["extensions"]["correlationId"].as_str().unwrap();
+        assert!(uuid::Uuid::parse_str(correlation_id).is_ok());
+    }
+
+    #[tokio::test]
+    async fn internal_errors_are_shown_when_masking_is_disabled() {
+        let config = GraphQLConfig {
+            mask_errors: false,
+            ..GraphQLConfig::default()
+        };
+        let schema = create_schema(&config, NoteStore::default());
+        let response = schema.execute(QUERY_WITHOUT_LOADER).await;
+
+        assert!(response.errors[0].message.contains("does not exist"));
+    }
+
+    #[tokio::test]
+    async fn query_over_get_is_executed() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let req = get_req_with_empty(Method::GET, "/?query=%7B%20hello%20%7D");
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res_json(res).await;
+        assert_eq!(body["data"]["hello"], "Hello World");
+        assert!(body["extensions"]["traceId"].is_string());
+    }
+
+    #[tokio::test]
+    async fn query_over_get_reads_encoded_variables() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let req = get_req_with_empty(
+            Method::GET,
+            "/?query=query%20Named%28%24id%3A%20ID%21%29%20%7B%20note%28id%3A%20%24id%29%20%7B%20id%20%7D%20%7D&variables=%7B%22id%22%3A%221%22%7D&operationName=Named",
+        );
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res_json(res).await;
+        assert!(body.get("errors").is_none());
+        assert_eq!(body["data"]["note"], Value::Null);
+        assert!(body["extensions"]["traceId"].is_string());
+    }
+
+    #[tokio::test]
+    async fn mutation_over_get_is_rejected() {
+        for config in [
+            GraphQLConfig::default(),
+            GraphQLConfig {
+                disable_introspection: true,
+                ..GraphQLConfig::default()
+            },
+        ] {
+            let app = test_create_full_app(config);
+            let req = get_req_with_empty(Method::GET, "/?query=mutation%20%7B%20deleteNote%28id%3A%20%221%22%29%20%7B%20id%20%7D%20%7D");
+            let res = app.oneshot(req).await.unwrap();
+
+            assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(res.headers()[header::ALLOW], "POST");
+            let body = res_json(res).await;
+            assert_eq!(body["errors"][0]["message"], "mutations must be sent with POST");
+        }
+    }
+
+    #[tokio::test]
+    async fn get_without_query_serves_the_playground() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/")).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+    }
+
+    async fn ide_page(ide: GraphQLIde) -> Response {
+        let app = test_create_full_app(GraphQLConfig {
+            ide,
+            ..GraphQLConfig::default()
+        });
+        app.oneshot(get_req_with_empty(Method::GET, "/")).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn ide_can_be_switched_to_graphiql() {
+        let playground = res_text(ide_page(GraphQLIde::Playground).await).await;
+        assert!(playground.contains("graphql-playground-react"));
+
+        let graphiql = res_text(ide_page(GraphQLIde::GraphiQL).await).await;
+        assert!(graphiql.contains("graphiql"));
+        assert!(!graphiql.contains("graphql-playground"));
+        assert!(graphiql.contains("/ws"));
+    }
+
+    #[tokio::test]
+    async fn ide_endpoints_follow_the_base_path() {
+        for ide in [GraphQLIde::Playground, GraphQLIde::GraphiQL] {
+            let app = test_create_full_app(GraphQLConfig {
+                ide,
+                base_path: "/api".into(),
+                ..GraphQLConfig::default()
+            });
+            let res = app.oneshot(get_req_with_empty(Method::GET, "/")).await.unwrap();
+            let html = res_text(res).await;
+
+            assert!(html.contains("/api/"), "{:?}", ide);
+            assert!(html.contains("/api/ws"), "{:?}", ide);
+        }
+    }
+
+    #[tokio::test]
+    async fn ide_can_be_turned_off() {
+        let res = ide_page(GraphQLIde::None).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res_json(res).await["error"], "not_found");
+    }
+
+    #[tokio::test]
+    async fn turning_the_ide_off_keeps_queries_working() {
+        let app = test_create_full_app(GraphQLConfig {
+            ide: GraphQLIde::None,
+            ..GraphQLConfig::default()
+        });
+
+        assert_eq!(graphql_data(&app, "{ hello }").await, json!({ "hello": "Hello World" }));
+    }
+
+    async fn protected_ide_status(authorization: Option<&str>) -> StatusCode {
+        let app = test_create_full_app(GraphQLConfig {
+            ide_credentials: Some(IdeCredentials {
+                user: "staging".into(),
+                password: "hunter2".into(),
+            }),
+            ..GraphQLConfig::default()
+        });
+        let mut req = get_req_with_empty(Method::GET, "/");
+        if let Some(authorization) = authorization {
+            req.headers_mut()
+                .insert(header::AUTHORIZATION, authorization.parse().unwrap());
+        }
+        let res = app.oneshot(req).await.unwrap();
+        if res.status() == StatusCode::UNAUTHORIZED {
+            assert_eq!(res.headers()[header::WWW_AUTHENTICATE], r#"Basic realm="GraphQL IDE""#);
+        }
+        res.status()
+    }
+
+    #[tokio::test]
+    async fn ide_can_require_basic_auth() {
+        assert_eq!(protected_ide_status(None).await, StatusCode::UNAUTHORIZED);
+        let wrong = format!("Basic {}", base64::encode("staging:guess"));
+        assert_eq!(protected_ide_status(Some(&wrong)).await, StatusCode::UNAUTHORIZED);
+        let right = format!("Basic {}", base64::encode("staging:hunter2"));
+        assert_eq!(protected_ide_status(Some(&right)).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn ide_credentials_leave_operations_alone() {
+        let app = test_create_full_app(GraphQLConfig {
+            ide_credentials: Some(IdeCredentials {
+                user: "staging".into(),
+                password: "hunter2".into(),
+            }),
+            ..GraphQLConfig::default()
+        });
+
+        assert_eq!(graphql_data(&app, "{ hello }").await["hello"], "Hello World");
+        let req = get_req_with_empty(Method::GET, "/?query=%7B%20hello%20%7D");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn apollo_tracing_reports_resolvers() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            apollo_tracing: true,
+            ..GraphQLConfig::default()
+        });
+        let res = app.oneshot(post_req_with_graphql("{ hello }")).await.unwrap();
+        let body = res_json(res).await;
+
+        let resolvers = body["extensions"]["tracing"]["execution"]["resolvers"]
+            .as_array()
+            .unwrap();
+        assert_eq!(resolvers[0]["fieldName"], "hello");
+        assert!(body["extensions"]["traceId"].is_string());
+    }
+
+    #[tokio::test]
+    async fn apollo_tracing_is_off_by_default() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let res = app.oneshot(post_req_with_graphql("{ hello }")).await.unwrap();
+        let body = res_json(res).await;
+
+        assert!(body["extensions"].get("tracing").is_none());
+    }
+
+    #[tokio::test]
+    async fn batched_operations_run_in_order() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let req = post_req_with_json(json!([
+            { "query": r#"mutation { createNote(title: "First", body: "") { id } }"# },
+            { "query": "{ notes { edges { node { title } } } }" },
+        ]));
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        assert_eq!(body[0]["data"]["createNote"]["id"], "Tm90ZTox");
+        assert_eq!(
+            body[1]["data"]["notes"]["edges"],
+            json!([{ "node": { "title": "First" } }])
+        );
+    }
+
+    #[tokio::test]
+    async fn now_is_an_rfc3339_date_time() {
+        let before = chrono::Utc::now();
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let res = app.oneshot(post_req_with_graphql("{ now }")).await.unwrap();
+        let body = res_json(res).await;
+
+        let now = chrono::DateTime::parse_from_rfc3339(body["data"]["now"].as_str().unwrap())
+            .unwrap();
+        assert!(now.with_timezone(&chrono::Utc) >= before);
+    }
+
+    /// Records the name of every span created while it is the default subscriber.
+    #[derive(Clone, Default)]
+    struct SpanNames(std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanNames {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            self.0.lock().unwrap().push(attrs.metadata().name());
+        }
+    }
+
+    #[tokio::test]
+    async fn field_tracing_emits_spans() {
+        let names = SpanNames::default();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(names.clone()));
+        let app = test_create_graphql_app(GraphQLConfig {
+            field_tracing: true,
+            ..GraphQLConfig::default()
+        });
+        app.oneshot(post_req_with_graphql("{ hello }")).await.unwrap();
+
+        let names = names.0.lock().unwrap();
+        for expected in ["graphql_execution", "parse", "validation", "field"] {
+            assert!(names.contains(&expected), "missing span {} in {:?}", expected, names);
+        }
+    }
+
+    fn write_allowlist(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("axum-graphql-{}.json", name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn test_create_allowlist_app(name: &str, allowlist_introspection: bool) -> Router {
+        use sha2::{Digest, Sha256};
+
+        let hash = format!("{:x}", Sha256::digest(b"{ hello }"));
+        let contents = format!(r#"{{ "{}": "{{ hello }}" }}"#, hash);
+        test_create_graphql_app(GraphQLConfig {
+            allowlist_file: Some(write_allowlist(name, &contents)),
+            allowlist_introspection,
+            ..GraphQLConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn allowlisted_hash_is_executed() {
+        use sha2::{Digest, Sha256};
+
+        let app = test_create_allowlist_app("allowed-hash", false);
+        let hash = format!("{:x}", Sha256::digest(b"{ hello }"));
+        let body = res_json(app.oneshot(apq_req(None, &hash)).await.unwrap()).await;
+
+        assert_eq!(body["data"]["hello"], "Hello World");
+    }
+
+    #[tokio::test]
+    async fn ad_hoc_query_is_not_allowed() {
+        let app = test_create_allowlist_app("ad-hoc", false);
+        let res = app.oneshot(post_req_with_graphql("{ now }")).await.unwrap();
+        let body = res_json(res).await;
+
+        assert_eq!(body["errors"][0]["extensions"]["code"], "QUERY_NOT_ALLOWED");
+        assert!(body["data"].is_null());
+    }
+
+    #[tokio::test]
+    async fn introspection_bypasses_the_allowlist_only_when_enabled() {
+        let query = "{ __schema { queryType { name } } }";
+
+        let app = test_create_allowlist_app("introspection-denied", false);
+        let body = res_json(app.oneshot(post_req_with_graphql(query)).await.unwrap()).await;
+        assert_eq!(body["errors"][0]["extensions"]["code"], "QUERY_NOT_ALLOWED");
+
+        let app = test_create_allowlist_app("introspection-allowed", true);
+        let body = res_json(app.oneshot(post_req_with_graphql(query)).await.unwrap()).await;
+        assert_eq!(body["data"]["__schema"]["queryType"]["name"], "QueryRoot");
+    }
+
+    #[tokio::test]
+    async fn allowlist_mode_stays_ready() {
+        let path = write_allowlist("ready", "{}");
+        let shutdown = Shutdown::new();
+        let app = test_create_full_app_with_shutdown(
+            GraphQLConfig {
+                allowlist_file: Some(path),
+                ..GraphQLConfig::default()
+            },
+            &shutdown,
+        );
+        shutdown.mark_ready();
+
+        for path in ["/readyz", "/health/ready", "/health"] {
+            let res = app.clone().oneshot(get_req_with_empty(Method::GET, path)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{}", path);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid allow-list")]
+    fn malformed_allowlist_fails_loudly() {
+        let config = GraphQLConfig {
+            allowlist_file: Some(write_allowlist("malformed", "[\"{ hello }\"]")),
+            ..GraphQLConfig::default()
+        };
+        create_schema(&config, NoteStore::default());
+    }
+
+    const TEST_JWT_SECRET: &[u8] = b"test-secret";
+
+    fn token_expiring_in(seconds: i64) -> String {
+        token_with_roles(seconds, &[])
+    }
+
+    fn token_with_roles(seconds: i64, roles: &[&str]) -> String {
+        let claims = crate::auth::Claims {
+            sub: "user-1".into(),
+            exp: (chrono::Utc::now().timestamp() + seconds) as usize,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        };
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(TEST_JWT_SECRET),
+        )
+        .unwrap()
+    }
+
+    fn authorized(mut req: Request<Body>, token: &str) -> Request<Body> {
+        req.headers_mut().insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        req
+    }
+
+    async fn query_as(token: &str, query: &str) -> Value {
+        let req = authorized(post_req_with_graphql(query), token);
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        res_json(app.oneshot(req).await.unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn valid_token_authenticates() {
+        let body = query_as(&token_expiring_in(3600), "{ me }").await;
+
+        assert_eq!(body["data"]["me"], "user-1");
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_rejected() {
+        let body = query_as(&token_expiring_in(-3600), "{ me }").await;
+
+        assert_eq!(body["errors"][0]["message"], "token has expired");
+        assert_eq!(body["errors"][0]["extensions"]["code"], "UNAUTHENTICATED");
+    }
+
+    #[tokio::test]
+    async fn malformed_token_only_fails_protected_fields() {
+        let body = query_as("not-a-jwt", "{ hello me }").await;
+
+        assert_eq!(body["data"]["hello"], "Hello World");
+        assert_eq!(body["errors"][0]["message"], "invalid token");
+        assert_eq!(body["errors"][0]["path"], json!(["me"]));
+        assert_eq!(body["errors"][0]["extensions"]["code"], "UNAUTHENTICATED");
+    }
+
+    #[tokio::test]
+    async fn protected_field_requires_a_token() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let res = app.oneshot(post_req_with_graphql("{ me }")).await.unwrap();
+        let body = res_json(res).await;
+
+        assert_eq!(body["errors"][0]["message"], "authentication required");
+    }
+
+    fn test_create_auth_app(required: bool) -> Router {
+        let auth = AuthConfig {
+            jwt_secret: Some(String::from_utf8(TEST_JWT_SECRET.to_vec()).unwrap()),
+            required,
+        };
+        let shutdown = Shutdown::new();
+        shutdown.mark_ready();
+        test_create_full_app_with(Config { auth, ..Config::default() }, &shutdown)
+    }
+
+    async fn auth_app_status(required: bool, token: Option<&str>) -> (StatusCode, Value) {
+        let req = post_req_with_graphql("{ me hello }");
+        let req = match token {
+            Some(token) => authorized(req, token),
+            None => req,
+        };
+        let res = test_create_auth_app(required).oneshot(req).await.unwrap();
+        (res.status(), res_json(res).await)
+    }
+
+    #[tokio::test]
+    async fn middleware_passes_valid_claims_to_resolvers() {
+        let (status, body) = auth_app_status(false, Some(&token_expiring_in(3600))).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["me"], "user-1");
+    }
+
+    #[tokio::test]
+    async fn middleware_rejects_expired_and_forged_tokens() {
+        let (status, body) = auth_app_status(false, Some(&token_expiring_in(-3600))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, json!({ "error": "unauthorized", "message": "token has expired" }));
+
+        let forged = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &crate::auth::Claims { sub: "user-1".into(), exp: usize::MAX, roles: Vec::new() },
+            &jsonwebtoken::EncodingKey::from_secret(b"another-secret"),
+        )
+        .unwrap();
+        let (status, body) = auth_app_status(false, Some(&forged)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["message"], "invalid token");
+    }
+
+    #[tokio::test]
+    async fn missing_token_is_anonymous_unless_required() {
+        let (status, body) = auth_app_status(false, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["errors"][0]["message"], "authentication required");
+        assert_eq!(body["errors"][0]["path"], json!(["me"]));
+
+        let (status, body) = auth_app_status(true, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["message"], "authentication required");
+
+        let health = test_create_auth_app(true)
+            .oneshot(get_req_with_empty(Method::GET, "/health"))
+            .await
+            .unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+    }
+
+    async fn ws_connect_with(
+        app: Router,
+        payload: Value,
+    ) -> WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>> {
+        let mut socket = connect_ws(spawn_server(app)).await;
+        let init = json!({ "type": "connection_init", "payload": payload });
+        socket.send(Message::Text(init.to_string())).await.unwrap();
+        socket
+    }
+
+    #[tokio::test]
+    async fn websocket_authenticates_with_the_connection_init_payload() {
+        let payload = json!({ "Authorization": format!("Bearer {}", token_expiring_in(3600)) });
+        let mut socket = ws_connect_with(test_create_auth_app(true), payload).await;
+        assert_eq!(next_ws_json(&mut socket).await["type"], "connection_ack");
+
+        let subscribe = json!({ "type": "subscribe", "id": "1", "payload": { "query": "{ me }" } });
+        socket.send(Message::Text(subscribe.to_string())).await.unwrap();
+        let next = next_ws_json(&mut socket).await;
+        assert_eq!(next["type"], "next");
+        assert_eq!(next["payload"]["data"]["me"], "user-1");
+    }
+
+    #[tokio::test]
+    async fn websocket_operations_get_the_request_data() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let req = post_req_with_graphql(r#"mutation { createNote(title: "Socket", body: "") { id } }"#);
+        let created = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        let id = &created["data"]["createNote"]["id"];
+        let mut socket = ws_connect_with(app, json!({})).await;
+        assert_eq!(next_ws_json(&mut socket).await["type"], "connection_ack");
+
+        let node = json!({ "query": NODE_QUERY, "variables": { "id": id } });
+        let subscribe = json!({ "type": "subscribe", "id": "1", "payload": node });
+        socket.send(Message::Text(subscribe.to_string())).await.unwrap();
+        let next = next_ws_json(&mut socket).await;
+        assert_eq!(next["payload"]["data"]["node"], json!({ "id": id, "title": "Socket" }));
+
+        assert_eq!(next_ws_json(&mut socket).await["type"], "complete");
+        let client_info = json!({ "query": "{ clientInfo { address userAgent } }" });
+        let subscribe = json!({ "type": "subscribe", "id": "2", "payload": client_info });
+        socket.send(Message::Text(subscribe.to_string())).await.unwrap();
+        let next = next_ws_json(&mut socket).await;
+        assert!(next["payload"].get("errors").is_none(), "{}", next);
+        assert!(next["payload"]["data"]["clientInfo"].is_object());
+    }
+
+    #[tokio::test]
+    async fn websocket_without_a_token_is_closed_when_auth_is_required() {
+        let mut socket = ws_connect_with(test_create_auth_app(true), json!({})).await;
+
+        match socket.next().await.unwrap().unwrap() {
+            Message::Close(frame) => assert_eq!(frame.unwrap().reason, "authentication required"),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn admin_role_passes_the_guard() {
+        let body = query_as(&token_with_roles(3600, &["admin"]), "{ authors { id } }").await;
+
+        assert!(body.get("errors").is_none(), "{}", body);
+        assert_eq!(body["data"]["authors"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn missing_role_is_forbidden() {
+        let body = query_as(&token_with_roles(3600, &["editor"]), "{ authors { id } }").await;
+
+        assert_eq!(body["errors"][0]["message"], "requires the 'admin' role");
+        assert_eq!(body["errors"][0]["extensions"]["code"], "FORBIDDEN");
+    }
+
+    async fn first_error_extensions(app: &Router, query: &str) -> Value {
+        let res = app.clone().oneshot(post_req_with_graphql(query)).await.unwrap();
+        res_json(res).await["errors"][0]["extensions"].clone()
+    }
+
+    #[tokio::test]
+    async fn invalid_arguments_are_rejected_before_the_resolver_runs() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            max_title_length: 5,
+            ..GraphQLConfig::default()
+        });
+
+        let rejected = [
+            (r#"mutation { createNote(title: "Too long", body: "") { id } }"#, "title"),
+            (r#"mutation { createNote(title: "Ok", body: "", slug: "Not A Slug") { id } }"#, "slug"),
+            (r#"mutation { createAuthor(name: "Ada", email: "ada.example.com") { id } }"#, "email"),
+            ("{ notes(first: 101) { edges { cursor } } }", "first"),
+        ];
+        for (query, field) in rejected {
+            assert_eq!(
+                first_error_extensions(&app, query).await,
+                json!({ "code": "BAD_USER_INPUT", "field": field }),
+                "{}",
+                query
+            );
+        }
+
+        // Nothing was stored by the rejected mutations.
+        let req = post_req_with_graphql(
+            r#"mutation {
+                createNote(title: "Ok", body: "", slug: "release-notes-2") { id slug }
+                createAuthor(name: "Ada", email: "ada@example.com") { id email }
+            }"#,
+        );
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+        assert_eq!(body["data"]["createNote"], json!({ "id": "Tm90ZTox", "slug": "release-notes-2" }));
+        assert_eq!(body["data"]["createAuthor"], json!({ "id": "QXV0aG9yOjE=", "email": "ada@example.com" }));
+    }
+
+    #[tokio::test]
+    async fn create_author_name_is_validated() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let too_long = format!(r#"mutation {{ createAuthor(name: "{}") {{ id }} }}"#, "a".repeat(101));
+        for query in [r#"mutation { createAuthor(name: "  ") { id } }"#, too_long.as_str()] {
+            assert_eq!(
+                first_error_extensions(&app, query).await,
+                json!({ "code": "BAD_USER_INPUT", "field": "name" }),
+                "{}",
+                query
+            );
+        }
+
+        let name = "a".repeat(100);
+        let query = format!(r#"mutation {{ createAuthor(name: "{}") {{ name }} }}"#, name);
+        assert_eq!(graphql_data(&app, &query).await["createAuthor"]["name"], name.as_str());
+    }
+
+    #[tokio::test]
+    async fn uppercase_directive_transforms_the_field() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let res = app
+            .oneshot(post_req_with_graphql("{ plain: hello shouted: hello @uppercase }"))
+            .await
+            .unwrap();
+        let body = res_json(res).await;
+
+        assert_eq!(body["data"], json!({ "plain": "Hello World", "shouted": "HELLO WORLD" }));
+    }
+
+    #[test]
+    fn uppercase_directive_is_in_the_sdl() {
+        assert!(test_schema().sdl().contains("directive @uppercase on FIELD"));
+    }
+
+    #[tokio::test]
+    async fn unknown_path_is_a_json_404() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/nope")).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let request_id = res.headers()["x-request-id"].to_str().unwrap().to_owned();
+        assert_eq!(
+            res_json(res).await,
+            json!({ "error": "not_found", "path": "/nope", "request_id": request_id })
+        );
+    }
+
+    #[tokio::test]
+    async fn wrong_method_is_a_json_405() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.oneshot(get_req_with_empty(Method::POST, "/health")).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let allow = res.headers()[header::ALLOW].to_str().unwrap().to_owned();
+        let body = res_json(res).await;
+        assert_eq!(body["error"], "method_not_allowed");
+        assert_eq!(body["path"], "/health");
+        let allowed: Vec<&str> = body["allowed"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|method| method.as_str().unwrap())
+            .collect();
+        assert!(allowed.contains(&"GET"), "{:?}", allowed);
+        assert_eq!(allowed.join(","), allow.replace(' ', ""));
+    }
+
+    async fn graphql_data(app: &Router, query: &str) -> Value {
+        let res = app.clone().oneshot(post_req_with_graphql(query)).await.unwrap();
+        res_json(res).await["data"].clone()
+    }
+
+    #[tokio::test]
+    async fn dynamic_fields_next_to_cacheable_ones_are_re_resolved() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let first = graphql_data(&app, "{ hello now }").await;
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        let second = graphql_data(&app, "{\n  hello\n  now\n}").await;
+
+        assert_eq!(first["hello"], "Hello World");
+        assert_ne!(first["now"], second["now"]);
+    }
+
+    #[tokio::test]
+    async fn only_fully_cacheable_selections_get_a_max_age() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let cache_control = |query: &'static str| {
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(post_req_with_graphql(query)).await.unwrap();
+                res.headers()
+                    .get(header::CACHE_CONTROL)
+                    .map(|value| value.to_str().unwrap().to_owned())
+            }
+        };
+
+        assert_eq!(cache_control("{ hello }").await.as_deref(), Some("max-age=60"));
+        for query in [
+            "{ hello clientInfo { address userAgent } }",
+            "{ hello notes { edges { node { title } } } }",
+            "{ hello now }",
+        ] {
+            let value = cache_control(query).await.unwrap_or_default();
+            assert!(!value.contains("max-age"), "{}: {}", query, value);
+        }
+    }
+
+    #[tokio::test]
+    async fn disabled_cache_executes_every_query() {
+        let app = test_create_full_app(GraphQLConfig {
+            response_cache: false,
+            ..GraphQLConfig::default()
+        });
+        let first = graphql_data(&app, "{ hello now }").await;
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        let second = graphql_data(&app, "{ hello now }").await;
+
+        assert_ne!(first["now"], second["now"]);
+    }
+
+    #[tokio::test]
+    async fn app_serves_the_mutation_and_subscription_roots() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let data = graphql_data(
+            &app,
+            "{ __schema { mutationType { name } subscriptionType { name } } }",
+        )
+        .await;
+
+        assert_eq!(
+            data["__schema"],
+            json!({
+                "mutationType": { "name": "MutationRoot" },
+                "subscriptionType": { "name": "SubscriptionRoot" },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn mutations_bypass_the_cache() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let mutation = r#"mutation { createAuthor(name: "Ada") { id } }"#;
+        let first = graphql_data(&app, mutation).await;
+        let second = graphql_data(&app, mutation).await;
+
+        assert_ne!(first["createAuthor"]["id"], second["createAuthor"]["id"]);
+    }
+
+    #[tokio::test]
+    async fn metrics_are_labelled_by_route_template() {
+        let app = Router::new()
+            .route("/notes/:id", get(health))
+            .route_layer(middleware::from_fn(track_metrics))
+            .fallback(
+                not_found
+                    .layer(middleware::from_fn(track_metrics))
+                    .into_service(),
+            );
+        let recorder = create_prometheus_recorder(&MetricsConfig::default().buckets);
+        for path in ["/notes/1", "/notes/2?draft=true", "/scan/wp-admin"] {
+            app.clone()
+                .oneshot(get_req_with_empty(Method::GET, path))
+                .await
+                .unwrap();
+        }
+
+        let output = recorder.render();
+        assert!(output.contains(r#"path="/notes/:id""#));
+        assert!(output.contains(r#"path="unknown""#));
+        assert!(!output.contains("/notes/1"));
+        assert!(!output.contains("wp-admin"));
+    }
+
+    #[tokio::test]
+    async fn resolver_errors_are_counted() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app
+            .clone()
+            .oneshot(post_req_with_graphql("query CountedFailure { me }"))
+            .await
+            .unwrap();
+        assert_eq!(res_json(res).await["errors"][0]["message"], "authentication required");
+        app.clone().oneshot(post_req_with_graphql("{ me }")).await.unwrap();
+
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/metrics")).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let output = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(output.contains(r#"graphql_errors_total{operation="CountedFailure"} 1"#));
+        assert!(output.contains(r#"graphql_errors_total{operation="anonymous"}"#));
+    }
+
+    #[tokio::test]
+    async fn operation_latency_is_labelled_by_name_and_type() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let queries = [
+            "query TimedHello { hello }",
+            r#"mutation TimedAuthor { createAuthor(name: "Ada") { id } }"#,
+        ];
+        for query in queries {
+            app.clone().oneshot(post_req_with_graphql(query)).await.unwrap();
+        }
+
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/metrics")).await.unwrap();
+        let output = res_text(res).await;
+        let has_series = |operation: &str, ty: &str| {
+            output.lines().any(|line| {
+                line.starts_with("graphql_operation_duration_seconds_count")
+                    && line.contains(&format!(r#"operation="{}""#, operation))
+                    && line.contains(&format!(r#"type="{}""#, ty))
+            })
+        };
+        assert!(has_series("TimedHello", "query"));
+        assert!(has_series("TimedAuthor", "mutation"));
+    }
+
+    const NODE_QUERY: &str = r#"query Node($id: ID!) {
+        node(id: $id) { id ... on Note { title } ... on Author { name } }
+    }"#;
+
+    async fn fetch_node(app: &Router, id: &Value) -> Value {
+        let req = post_req_with_json(json!({ "query": NODE_QUERY, "variables": { "id": id } }));
+        res_json(app.clone().oneshot(req).await.unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn listed_objects_are_refetched_by_global_id() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let req = post_req_with_graphql(
+            r#"mutation {
+                createAuthor(name: "Ada") { id }
+                createNote(title: "Relay", body: "", authorId: "1") { id }
+            }"#,
+        );
+        res_json(app.clone().oneshot(req).await.unwrap()).await;
+        let req = post_req_with_graphql("{ notes { edges { node { id author { id } } } } }");
+        let listed = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        let note = &listed["data"]["notes"]["edges"][0]["node"];
+
+        let fetched = fetch_node(&app, &note["id"]).await;
+        assert_eq!(fetched["data"]["node"], json!({ "id": note["id"], "title": "Relay" }));
+
+        let fetched = fetch_node(&app, &note["author"]["id"]).await;
+        assert_eq!(
+            fetched["data"]["node"],
+            json!({ "id": note["author"]["id"], "name": "Ada" })
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_global_ids_resolve_to_null() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        // "Unknown:1" and "Note:99".
+        for id in ["VW5rbm93bjox", "Tm90ZTo5OQ=="] {
+            let body = fetch_node(&app, &json!(id)).await;
+
+            assert_eq!(body["data"]["node"], Value::Null);
+            assert!(body.get("errors").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_global_id_is_bad_user_input() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let body = fetch_node(&app, &json!("not base64!")).await;
+
+        assert_eq!(body["errors"][0]["extensions"]["code"], "BAD_USER_INPUT");
+        assert_eq!(body["errors"][0]["extensions"]["field"], "id");
+    }
+
+    #[tokio::test]
+    async fn in_flight_requests_are_tracked_until_dropped() {
+        let release = std::sync::Arc::new(tokio::sync::Notify::new());
+        let app = Router::new()
+            .route(
+                "/held",
+                get({
+                    let release = release.clone();
+                    move || async move {
+                        release.notified().await;
+                    }
+                }),
+            )
+            .route_layer(middleware::from_fn(track_metrics));
+        let recorder = create_prometheus_recorder(&MetricsConfig::default().buckets);
+        let gauge = |value: &str| format!(r#"http_requests_in_flight{{path="/held"}} {}"#, value);
+
+        let held = tokio::spawn(app.oneshot(get_req_with_empty(Method::GET, "/held")));
+        for _ in 0..100 {
+            if recorder.render().contains(&gauge("1")) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(recorder.render().contains(&gauge("1")));
+
+        // Cancelling the request drops the guard without a response.
+        held.abort();
+        let _ = held.await;
+        assert!(recorder.render().contains(&gauge("0")));
+    }
+
+    #[tokio::test]
+    async fn role_guards_tell_missing_principal_from_missing_role() {
+        use crate::auth::{Authentication, Claims};
+
+        let schema = create_schema(&GraphQLConfig::default(), NoteStore::default());
+        let principal = |roles: &[&str]| {
+            Authentication::Authenticated(Claims {
+                sub: "user-1".into(),
+                exp: usize::MAX,
+                roles: roles.iter().map(|role| role.to_string()).collect(),
+            })
+        };
+        let outcomes = [
+            (None, Some("UNAUTHENTICATED")),
+            (Some(principal(&["viewer"])), Some("FORBIDDEN")),
+            (Some(principal(&["admin"])), None),
+        ];
+        for query in ["{ authors { id } }", r#"mutation { deleteNote(id: "1") { id } }"#] {
+            for (auth, code) in outcomes.clone() {
+                let mut request = async_graphql::Request::new(query);
+                if let Some(auth) = auth {
+                    request = request.data(auth);
+                }
+                let body = serde_json::to_value(schema.execute(request).await).unwrap();
+                let actual = body["errors"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|error| error["message"] != "note '1' not found")
+                    .map(|error| error["extensions"]["code"].clone());
+
+                assert_eq!(actual, code.map(|code| json!(code)), "{}", query);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn client_info_reads_allow_listed_request_details() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let mut req = post_req_with_graphql("{ clientInfo { userAgent acceptLanguage address } }");
+        let headers = req.headers_mut();
+        headers.insert(header::USER_AGENT, "test-client/1.0".parse().unwrap());
+        headers.insert(header::ACCEPT_LANGUAGE, "ja-JP".parse().unwrap());
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 7], 40000))));
+        let body = res_json(app.oneshot(req).await.unwrap()).await;
+
+        assert_eq!(
+            body["data"]["clientInfo"],
+            json!({
+                "userAgent": "test-client/1.0",
+                "acceptLanguage": "ja-JP",
+                "address": "10.0.0.7:40000",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn request_context_drops_unlisted_headers() {
+        use crate::context::RequestContext;
+        use axum::extract::{FromRequest, RequestParts};
+
+        let req = Request::builder()
+            .header(header::COOKIE, "session=secret")
+            .header(header::USER_AGENT, "test-client/1.0")
+            .body(Body::empty())
+            .unwrap();
+        let context = RequestContext::from_request(&mut RequestParts::new(req))
+            .await
+            .unwrap();
+
+        assert!(context.headers.get(header::COOKIE).is_none());
+        assert_eq!(context.header(header::USER_AGENT), Some("test-client/1.0"));
+        assert!(context.remote_addr.is_none());
+    }
+
+    fn multipart_upload_req(files: &[Vec<u8>]) -> Request<Body> {
+        let names: Vec<String> = (0..files.len()).map(|n| format!("f{}", n)).collect();
+        let query = format!(
+            "mutation({}) {{ {} }}",
+            names.iter().map(|name| format!("${}: Upload!", name)).collect::<Vec<_>>().join(", "),
+            names
+                .iter()
+                .map(|name| format!("{}: uploadFile(file: ${}) {{ size }}", name, name))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        let variables: serde_json::Map<String, Value> =
+            names.iter().map(|name| (name.clone(), Value::Null)).collect();
+        let map: serde_json::Map<String, Value> = names
+            .iter()
+            .enumerate()
+            .map(|(n, name)| (n.to_string(), json!([format!("variables.{}", name)])))
+            .collect();
+
+        let mut body = format!(
+            "--boundary\r\n\
+             Content-Disposition: form-data; name=\"operations\"\r\n\r\n\
+             {}\r\n\
+             --boundary\r\n\
+             Content-Disposition: form-data; name=\"map\"\r\n\r\n\
+             {}\r\n",
+            json!({ "query": query, "variables": variables }),
+            Value::Object(map)
+        )
+        .into_bytes();
+        for (n, file) in files.iter().enumerate() {
+            body.extend(
+                format!(
+                    "--boundary\r\n\
+                     Content-Disposition: form-data; name=\"{}\"; filename=\"{}.bin\"\r\n\
+                     Content-Type: application/octet-stream\r\n\r\n",
+                    n, n
+                )
+                .into_bytes(),
+            );
+            body.extend(file);
+            body.extend(b"\r\n");
+        }
+        body.extend(b"--boundary--\r\n");
+
+        Request::builder()
+            .uri("/")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=boundary")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn test_create_upload_app(max_upload_files: Option<usize>) -> Router {
+        test_create_graphql_app(GraphQLConfig {
+            max_upload_size: 1024,
+            max_upload_files,
+            max_upload_request_size: 4096,
+            ..GraphQLConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn upload_at_the_size_limit_is_accepted() {
+        let req = multipart_upload_req(&[vec![b'a'; 1024]]);
+        let res = test_create_upload_app(None).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res_json(res).await["data"]["f0"]["size"], 1024);
+    }
+
+    #[tokio::test]
+    async fn upload_over_the_size_limit_is_rejected() {
+        let req = multipart_upload_req(&[vec![b'a'; 1025]]);
+        let res = test_create_upload_app(None).oneshot(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            res_json(res).await["errors"][0]["message"],
+            "file exceeds the upload limit of 1024 bytes"
+        );
+    }
+
+    #[tokio::test]
+    async fn uploads_over_the_file_count_are_rejected() {
+        let app = test_create_upload_app(Some(1));
+
+        let res = app.clone().oneshot(multipart_upload_req(&[vec![b'a'; 8]])).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = multipart_upload_req(&[vec![b'a'; 8], vec![b'b'; 8]]);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res_json(res).await["errors"][0]["message"],
+            "request uploads more files than the limit of 1"
+        );
+    }
+
+    #[tokio::test]
+    async fn uploads_over_the_request_size_are_rejected() {
+        let files = vec![vec![b'a'; 1000]; 5];
+        let res = test_create_upload_app(None).oneshot(multipart_upload_req(&files)).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            res_json(res).await["errors"][0]["message"],
+            "request body exceeds the limit of 4096 bytes"
+        );
+    }
+
+    /// Collects the fields recorded on `graphql_execution` spans.
+    #[derive(Clone, Default)]
+    struct ExecutionSpanFields {
+        spans: std::sync::Arc<std::sync::Mutex<Vec<tracing::span::Id>>>,
+        fields: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, String>>>,
+    }
+
+    impl tracing::field::Visit for ExecutionSpanFields {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.fields
+                .lock()
+                .unwrap()
+                .insert(field.name().to_owned(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.fields
+                .lock()
+                .unwrap()
+                .insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for ExecutionSpanFields {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if attrs.metadata().name() == "graphql_execution" {
+                self.spans.lock().unwrap().push(id.clone());
+            }
+        }
+
+        fn on_record(
+            &self,
+            id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if self.spans.lock().unwrap().contains(id) {
+                values.record(&mut self.clone());
+            }
+        }
+    }
+
+    async fn execution_span_fields(body: Value) -> std::collections::HashMap<String, String> {
+        let fields = ExecutionSpanFields::default();
+        let guard = tracing::subscriber::set_default(Registry::default().with(fields.clone()));
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        app.oneshot(post_req_with_json(body)).await.unwrap();
+
+        let recorded = fields.fields.lock().unwrap().clone();
+        drop(guard);
+        recorded
+    }
+
+    #[tokio::test]
+    async fn execution_span_names_the_operation() {
+        let query = "query GetNotes { notes { edges { node { id } } } }";
+        let fields = execution_span_fields(json!({ "query": query })).await;
+
+        assert_eq!(fields["otel.name"], "query GetNotes");
+        assert_eq!(fields["graphql.operation.name"], "GetNotes");
+        assert_eq!(fields["graphql.operation.type"], "query");
+        assert_eq!(fields["graphql.document.length"], query.len().to_string());
+        assert_eq!(fields["graphql.batch"], "false");
+    }
+
+    #[tokio::test]
+    async fn execution_span_labels_anonymous_operations() {
+        let body = json!({ "query": r#"mutation { createAuthor(name: "Ada") { id } }"# });
+        let fields = execution_span_fields(body).await;
+
+        assert_eq!(fields["otel.name"], "mutation anonymous");
+        assert_eq!(fields["graphql.operation.name"], "anonymous");
+        assert_eq!(fields["graphql.operation.type"], "mutation");
+    }
+
+    #[tokio::test]
+    async fn execution_span_marks_batches() {
+        let fields =
+            execution_span_fields(json!([{ "query": "{ hello }" }, { "query": "{ now }" }])).await;
+
+        assert_eq!(fields["otel.name"], "batch");
+        assert_eq!(fields["graphql.batch"], "true");
+        assert_eq!(fields["graphql.document.length"], "16");
+    }
+
+    #[tokio::test]
+    async fn traceparent_header_continues_the_upstream_trace() {
+        use opentelemetry::trace::TracerProvider as _;
+
+        let provider = opentelemetry::sdk::trace::TracerProvider::builder().build();
+        let subscriber = Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let app = test_create_graphql_app(GraphQLConfig::default());
+
+        let mut req = post_req_with_graphql("{ hello }");
+        req.headers_mut().insert(
+            "traceparent",
+            HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        );
+        let traced = res_json(app.clone().oneshot(req).await.unwrap()).await;
+        assert_eq!(traced["extensions"]["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+
+        let fresh = res_json(app.oneshot(post_req_with_graphql("{ hello }")).await.unwrap()).await;
+        assert_ne!(fresh["extensions"]["traceId"], traced["extensions"]["traceId"]);
+    }
+
+    #[tokio::test]
+    async fn trace_id_is_sent_as_a_header() {
+        let app = test_create_graphql_app(GraphQLConfig::default());
+        let res = app.oneshot(post_req_with_graphql("{ hello }")).await.unwrap();
+
+        let header = res.headers()["x-trace-id"].to_str().unwrap().to_owned();
+        let body = res_json(res).await;
+        assert_eq!(header.len(), 32);
+        assert_eq!(body["extensions"]["traceId"], header.as_str());
+    }
+
+    #[tokio::test]
+    async fn request_id_is_echoed_in_the_header_and_extensions() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let mut req = post_req_with_graphql("{ hello }");
+        req.headers_mut().insert("x-request-id", HeaderValue::from_static("client-42"));
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.headers()["x-request-id"], "client-42");
+        assert_eq!(res_json(res).await["extensions"]["requestId"], "client-42");
+    }
+
+    #[tokio::test]
+    async fn request_id_is_generated_when_absent() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let res = app.oneshot(get_req_with_empty(Method::GET, "/health")).await.unwrap();
+
+        let request_id = res.headers()["x-request-id"].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(request_id).is_ok(), "{}", request_id);
+    }
+
+    #[tokio::test]
+    async fn request_id_is_in_route_errors() {
+        let app = test_create_full_app(GraphQLConfig::default());
+        let mut req = get_req_with_empty(Method::POST, "/health");
+        req.headers_mut().insert("x-request-id", HeaderValue::from_static("client-42"));
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(res.headers()["x-request-id"], "client-42");
+        assert_eq!(res_json(res).await["request_id"], "client-42");
+    }
+
+    #[tokio::test]
+    async fn alias_bombs_are_rejected_before_resolving() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            max_aliases: Some(10),
+            max_root_fields: None,
+            ..GraphQLConfig::default()
+        });
+        let aliases: Vec<String> = (0..1000)
+            .map(|n| format!(r#"a{}: createAuthor(name: "Ada") {{ id }}"#, n))
+            .collect();
+        let mutation = format!("mutation {{ {} }}", aliases.join(" "));
+        let body = res_json(app.clone().oneshot(post_req_with_graphql(&mutation)).await.unwrap()).await;
+
+        assert_eq!(body["errors"][0]["message"], "operation uses 1000 aliases, more than the limit of 10");
+        assert_eq!(body["errors"][0]["extensions"]["code"], "TOO_MANY_ALIASES");
+
+        let req = authorized(
+            post_req_with_graphql("{ authors { id } }"),
+            &token_with_roles(3600, &["admin"]),
+        );
+        let authors = res_json(app.oneshot(req).await.unwrap()).await;
+        assert_eq!(authors["data"]["authors"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn root_fields_are_limited_through_fragments() {
+        let app = test_create_graphql_app(GraphQLConfig {
+            max_root_fields: Some(2),
+            ..GraphQLConfig::default()
+        });
+        let query = "query { hello ...Clock } fragment Clock on QueryRoot { now t: now }";
+        let body = res_json(app.oneshot(post_req_with_graphql(query)).await.unwrap()).await;
+
+        assert_eq!(body["errors"][0]["message"], "operation selects 3 root fields, more than the limit of 2");
+        assert_eq!(body["errors"][0]["extensions"]["code"], "TOO_MANY_ROOT_FIELDS");
+    }
+}
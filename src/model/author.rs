@@ -0,0 +1,106 @@
+use super::node::{global_id, local_id};
+use super::AppError;
+use crate::auth::RoleGuard;
+use async_graphql::{ComplexObject, Context, Object, SimpleObject, ID};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+#[derive(SimpleObject, Clone, Debug)]
+#[graphql(complex)]
+pub(crate) struct Author {
+    #[graphql(skip)]
+    pub id: ID,
+    pub name: String,
+    pub email: Option<String>,
+}
+
+#[ComplexObject]
+impl Author {
+    /// Global id, accepted by `Query.node` and wherever an author id is expected.
+    async fn id(&self) -> ID {
+        global_id("Author", &self.id)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct AuthorQuery;
+
+#[Object]
+impl AuthorQuery {
+    /// Every author, in creation order; restricted to admins.
+    #[graphql(guard = "RoleGuard::new(\"admin\")", cache_control(no_cache))]
+    async fn authors(&self, ctx: &Context<'_>) -> Result<Vec<Author>, AppError> {
+        Ok(ctx.data::<AuthorStore>()?.list().await)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct AuthorMutation;
+
+#[Object]
+impl AuthorMutation {
+    async fn create_author(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(max_length = 100))] name: String,
+        #[graphql(validator(email))] email: Option<String>,
+    ) -> Result<Author, AppError> {
+        if name.trim().is_empty() {
+            return Err(AppError::InvalidInput {
+                field: Some("name"),
+                message: "name must not be empty".into(),
+            });
+        }
+        Ok(ctx.data::<AuthorStore>()?.insert(name, email).await)
+    }
+}
+
+/// In-memory author storage shared through the schema data.
+#[derive(Clone, Default)]
+pub(crate) struct AuthorStore {
+    authors: Arc<RwLock<HashMap<u64, Author>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl AuthorStore {
+    pub async fn contains(&self, id: &ID) -> bool {
+        match parse_id(id) {
+            Some(key) => self.authors.read().await.contains_key(&key),
+            None => false,
+        }
+    }
+
+    pub async fn list(&self) -> Vec<Author> {
+        let authors = self.authors.read().await;
+        let mut keys: Vec<&u64> = authors.keys().collect();
+        keys.sort();
+        keys.into_iter().map(|key| authors[key].clone()).collect()
+    }
+
+    pub async fn get_many(&self, ids: &[ID]) -> HashMap<ID, Author> {
+        let authors = self.authors.read().await;
+        ids.iter()
+            .filter_map(|id| {
+                let author = authors.get(&parse_id(id)?)?;
+                Some((id.clone(), author.clone()))
+            })
+            .collect()
+    }
+
+    pub async fn insert(&self, name: String, email: Option<String>) -> Author {
+        let key = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let author = Author {
+            id: ID(key.to_string()),
+            name,
+            email,
+        };
+        self.authors.write().await.insert(key, author.clone());
+        author
+    }
+}
+
+fn parse_id(id: &ID) -> Option<u64> {
+    local_id("Author", id).parse().ok()
+}
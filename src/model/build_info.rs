@@ -0,0 +1,19 @@
+use async_graphql::SimpleObject;
+use serde::Serialize;
+
+/// What was deployed, as baked in by `build.rs`.
+#[derive(SimpleObject, Serialize, Clone, Copy)]
+pub(crate) struct BuildInfo {
+    pub version: &'static str,
+    pub commit: &'static str,
+    /// When the binary was built, as RFC 3339.
+    pub build_timestamp: &'static str,
+    pub rustc: &'static str,
+}
+
+pub(crate) const BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    commit: env!("GIT_COMMIT"),
+    build_timestamp: env!("BUILD_TIMESTAMP"),
+    rustc: env!("RUSTC_VERSION"),
+};
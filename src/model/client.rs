@@ -0,0 +1,22 @@
+use crate::context::RequestContext;
+use async_graphql::SimpleObject;
+use axum::http::header;
+
+/// What the server knows about the calling HTTP client.
+#[derive(SimpleObject)]
+pub(crate) struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub accept_language: Option<String>,
+    /// Peer address of the connection, which may be a proxy.
+    pub address: Option<String>,
+}
+
+impl From<&RequestContext> for ClientInfo {
+    fn from(context: &RequestContext) -> Self {
+        ClientInfo {
+            user_agent: context.header(header::USER_AGENT).map(String::from),
+            accept_language: context.header(header::ACCEPT_LANGUAGE).map(String::from),
+            address: context.remote_addr.map(|addr| addr.to_string()),
+        }
+    }
+}
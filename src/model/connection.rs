@@ -0,0 +1,158 @@
+use super::AppError;
+use async_graphql::connection::{query, Connection, CursorType, Edge};
+use async_graphql::{Error, OutputType};
+
+/// Page size used when neither `first` nor `last` is given.
+pub(crate) const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// Opaque cursor holding the base64-encoded offset of an edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct OffsetCursor(pub usize);
+
+impl CursorType for OffsetCursor {
+    type Error = &'static str;
+
+    fn decode_cursor(s: &str) -> Result<Self, Self::Error> {
+        let bytes = base64::decode(s).map_err(|_| "cursor is not valid base64")?;
+        let offset = String::from_utf8(bytes).map_err(|_| "cursor is not an offset")?;
+        offset
+            .parse()
+            .map(OffsetCursor)
+            .map_err(|_| "cursor is not an offset")
+    }
+
+    fn encode_cursor(&self) -> String {
+        base64::encode(self.0.to_string())
+    }
+}
+
+impl From<usize> for OffsetCursor {
+    fn from(offset: usize) -> Self {
+        OffsetCursor(offset)
+    }
+}
+
+impl From<OffsetCursor> for usize {
+    fn from(cursor: OffsetCursor) -> Self {
+        cursor.0
+    }
+}
+
+/// Slices `items` into a Relay connection page. Cursors are offsets into
+/// `items`, so the order must be stable between requests; a cursor past the
+/// end gives an empty page.
+pub(crate) async fn paginate<C, T>(
+    items: Vec<T>,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> Result<Connection<C, T>, AppError>
+where
+    C: CursorType + From<usize> + Into<usize> + Send + Sync,
+    <C as CursorType>::Error: std::fmt::Display + Send + Sync + 'static,
+    T: OutputType,
+{
+    query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<C>, before: Option<C>, first, last| async move {
+            let total = items.len();
+            let mut end = before.map_or(total, Into::into).min(total);
+            let mut start = after
+                .map_or(0, |after| Into::<usize>::into(after).saturating_add(1))
+                .min(end);
+            match (first, last) {
+                (Some(first), _) => end = end.min(start + first),
+                (None, Some(last)) => start = start.max(end.saturating_sub(last)),
+                (None, None) => end = end.min(start + DEFAULT_PAGE_SIZE),
+            }
+
+            let mut connection = Connection::new(start > 0, end < total);
+            connection.edges.extend(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .skip(start)
+                    .take(end - start)
+                    .map(|(offset, item)| Edge::new(C::from(offset), item)),
+            );
+            Ok::<_, Error>(connection)
+        },
+    )
+    .await
+    .map_err(|error| AppError::InvalidInput {
+        field: None,
+        message: error.message,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn titles(
+        items: &[&'static str],
+        after: Option<String>,
+        first: Option<i32>,
+    ) -> (Vec<&'static str>, bool, bool) {
+        let connection: Connection<OffsetCursor, &'static str> =
+            paginate(items.to_vec(), after, None, first, None)
+                .await
+                .unwrap();
+        let titles = connection.edges.iter().map(|edge| edge.node).collect();
+        (
+            titles,
+            connection.has_previous_page,
+            connection.has_next_page,
+        )
+    }
+
+    #[test]
+    fn cursors_are_opaque_offsets() {
+        let cursor = OffsetCursor(3).encode_cursor();
+        assert_eq!(cursor, "Mw==");
+        assert_eq!(OffsetCursor::decode_cursor(&cursor), Ok(OffsetCursor(3)));
+        assert!(OffsetCursor::decode_cursor("3").is_err());
+    }
+
+    #[tokio::test]
+    async fn pages_forward() {
+        let items = ["a", "b", "c"];
+        assert_eq!(
+            titles(&items, None, Some(2)).await,
+            (vec!["a", "b"], false, true)
+        );
+
+        let after = Some(OffsetCursor(1).encode_cursor());
+        assert_eq!(
+            titles(&items, after, Some(2)).await,
+            (vec!["c"], true, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_items_give_an_empty_page() {
+        assert_eq!(titles(&[], None, Some(2)).await, (vec![], false, false));
+    }
+
+    #[tokio::test]
+    async fn cursor_past_the_end_gives_an_empty_page() {
+        let after = Some(OffsetCursor(10).encode_cursor());
+        assert_eq!(
+            titles(&["a", "b"], after, Some(2)).await,
+            (vec![], true, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn largest_cursor_gives_an_empty_page() {
+        let after = Some(OffsetCursor(usize::MAX).encode_cursor());
+        assert_eq!(
+            titles(&["a", "b"], after, Some(2)).await,
+            (vec![], true, false)
+        );
+    }
+}
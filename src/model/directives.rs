@@ -0,0 +1,24 @@
+use async_graphql::{Context, CustomDirective, Directive, ResolveFut, ServerResult, Value};
+use async_trait::async_trait;
+
+struct UppercaseDirective;
+
+#[async_trait]
+impl CustomDirective for UppercaseDirective {
+    async fn resolve_field(
+        &self,
+        _ctx: &Context<'_>,
+        resolve: ResolveFut<'_>,
+    ) -> ServerResult<Option<Value>> {
+        Ok(resolve.await?.map(|value| match value {
+            Value::String(value) => Value::String(value.to_uppercase()),
+            value => value,
+        }))
+    }
+}
+
+/// Uppercases the result of a string field, e.g. `{ hello @uppercase }`.
+#[Directive(location = "field")]
+pub(crate) fn uppercase() -> impl CustomDirective {
+    UppercaseDirective
+}
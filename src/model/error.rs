@@ -0,0 +1,77 @@
+use async_graphql::{Error, ErrorExtensions};
+use std::convert::Infallible;
+
+/// Errors returned by the resolvers. Each variant is reported with a stable
+/// `extensions.code` that clients can branch on instead of the message.
+#[derive(Clone, Debug)]
+pub(crate) enum AppError {
+    NotFound {
+        resource: &'static str,
+        id: String,
+    },
+    InvalidInput {
+        field: Option<&'static str>,
+        message: String,
+    },
+    Unauthorized(String),
+    Forbidden(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "NOT_FOUND",
+            AppError::InvalidInput { .. } => "BAD_USER_INPUT",
+            AppError::Unauthorized(_) => "UNAUTHENTICATED",
+            AppError::Forbidden(_) => "FORBIDDEN",
+            AppError::Internal(_) => "INTERNAL_SERVER_ERROR",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::NotFound { resource, id } => format!("{} '{}' not found", resource, id),
+            AppError::InvalidInput { message, .. } => message.clone(),
+            AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::Internal(message) => message.clone(),
+        }
+    }
+}
+
+impl ErrorExtensions for AppError {
+    fn extend(&self) -> Error {
+        Error::new(self.message()).extend_with(|_, extensions| {
+            extensions.set("code", self.code());
+            if let AppError::InvalidInput {
+                field: Some(field), ..
+            } = self
+            {
+                extensions.set("field", *field);
+            }
+        })
+    }
+}
+
+// `AppError` deliberately doesn't implement `Display`: async-graphql converts
+// every `Display` type into a bare-message `Error`, which would drop the code.
+impl From<AppError> for Error {
+    fn from(error: AppError) -> Self {
+        error.extend()
+    }
+}
+
+/// Failures of the async-graphql machinery itself, such as missing context
+/// data, are not the client's fault.
+impl From<Error> for AppError {
+    fn from(error: Error) -> Self {
+        AppError::Internal(error.message)
+    }
+}
+
+impl From<Infallible> for AppError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
@@ -1,12 +1,119 @@
-use async_graphql::{Context, Object, Schema};
-use async_graphql::{EmptyMutation, EmptySubscription};
+use crate::config::GraphQLConfig;
+use crate::extensions::allowlist::AllowList;
+use crate::extensions::apq::MeteredCacheStorage;
+use crate::extensions::input_errors::BadUserInput;
+use crate::extensions::mask_errors::MaskErrors;
+use crate::extensions::selection_limits::SelectionLimits;
+use crate::loaders::AuthorLoader;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::extensions::{ApolloPersistedQueries, ApolloTracing, Tracing};
+use async_graphql::{MergedObject, Schema, SchemaBuilder, Subscription};
+use futures_util::stream::{self, Stream};
+use std::time::Duration;
 
-pub(crate) type ServiceSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
-pub(crate) struct QueryRoot;
+mod author;
+mod build_info;
+mod client;
+mod connection;
+mod directives;
+mod error;
+mod node;
+mod note;
+mod reminder;
+mod system;
+mod upload;
+mod validators;
 
-#[Object]
-impl QueryRoot {
-    async fn hello(&self, _ctx: &Context<'_>) -> &'static str {
-        "Hello World"
+pub(crate) use author::{Author, AuthorMutation, AuthorQuery, AuthorStore};
+pub(crate) use build_info::{BuildInfo, BUILD_INFO};
+pub(crate) use client::ClientInfo;
+pub(crate) use error::AppError;
+pub(crate) use node::{Node, NodeQuery};
+pub(crate) use note::{Note, NoteMutation, NoteQuery, NoteStore};
+pub(crate) use reminder::{
+    Reminder, ReminderInput, ReminderMutation, ReminderQuery, ReminderStore,
+};
+pub(crate) use system::SystemQuery;
+pub(crate) use upload::{UploadMutation, UploadedFile};
+pub(crate) use validators::{InputLimits, MaxLength, SlugValidator};
+
+pub(crate) type ServiceSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+type ServiceSchemaBuilder = SchemaBuilder<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+pub(crate) fn create_schema(config: &GraphQLConfig, store: NoteStore) -> ServiceSchema {
+    let authors = AuthorStore::default();
+    let author_loader = DataLoader::new(AuthorLoader::new(authors.clone()), tokio::spawn)
+        .delay(config.loader_delay)
+        .max_batch_size(config.loader_max_batch_size);
+    let builder = Schema::build(QueryRoot::default(), MutationRoot::default(), SubscriptionRoot)
+        .data(store)
+        .data(authors)
+        .data(author_loader)
+        .directive(directives::uppercase)
+        .data(InputLimits {
+            max_title_length: config.max_title_length,
+            max_body_length: config.max_body_length,
+        });
+    let mut builder = reminder::register(builder);
+    // Masking wraps the other extensions, so it sees the codes they assign.
+    if config.mask_errors {
+        builder = builder.extension(MaskErrors);
+    }
+    builder = builder.extension(BadUserInput);
+    builder = builder.extension(SelectionLimits {
+        max_aliases: config.max_aliases,
+        max_root_fields: config.max_root_fields,
+        max_depth: config.max_depth,
+        max_complexity: config.max_complexity,
+    });
+    if config.disable_introspection {
+        builder = builder.disable_introspection();
+    }
+    if config.federation {
+        builder = builder.enable_federation();
+    }
+    if config.apollo_tracing {
+        builder = builder.extension(ApolloTracing);
+    }
+    if config.field_tracing {
+        builder = builder.extension(Tracing);
+    }
+    // Checked ahead of APQ so unlisted queries are never cached.
+    if let Some(path) = &config.allowlist_file {
+        builder = builder.extension(AllowList::load(path, config.allowlist_introspection));
+    }
+    if let Some(capacity) = config.apq_cache_size {
+        builder = builder.extension(ApolloPersistedQueries::new(MeteredCacheStorage::new(
+            capacity,
+        )));
+    }
+    builder.finish()
+}
+
+/// The query root, merged from one object per domain module.
+///
+/// A new module defines its own `#[Object]` with `#[derive(Default)]`, adds
+/// it here (or to `MutationRoot`) and, like `reminder::register`, adds the
+/// data its resolvers read in `create_schema`.
+#[derive(MergedObject, Default)]
+pub(crate) struct QueryRoot(SystemQuery, AuthorQuery, NodeQuery, NoteQuery, ReminderQuery);
+
+#[derive(MergedObject, Default)]
+pub(crate) struct MutationRoot(AuthorMutation, NoteMutation, ReminderMutation, UploadMutation);
+
+pub(crate) struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Emits an increasing counter every `n` seconds.
+    async fn interval(
+        &self,
+        #[graphql(default = 1, validator(minimum = 1))] n: u64,
+    ) -> impl Stream<Item = i64> {
+        let period = Duration::from_secs(n);
+        stream::unfold(0, move |count| async move {
+            tokio::time::sleep(period).await;
+            Some((count + 1, count + 1))
+        })
     }
-}
\ No newline at end of file
+}
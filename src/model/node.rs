@@ -0,0 +1,70 @@
+use super::{AppError, Author, Note};
+use crate::loaders::{AuthorLoader, NoteLoader};
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{Context, Interface, Object, ID};
+use std::borrow::Cow;
+
+/// An object clients can re-fetch through `Query.node` by its global id.
+#[derive(Interface)]
+#[graphql(field(name = "id", type = "ID"))]
+pub(crate) enum Node {
+    Note(Note),
+    Author(Author),
+}
+
+#[derive(Default)]
+pub(crate) struct NodeQuery;
+
+#[Object]
+impl NodeQuery {
+    /// Fetches any `Node` by the global id it was returned with; ids of
+    /// unknown types or objects resolve to `null`.
+    #[graphql(cache_control(no_cache))]
+    async fn node(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Node>, AppError> {
+        let (type_name, local_id) = match decode_global_id(&id)? {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        let local_id = ID(local_id);
+        Ok(match type_name.as_str() {
+            "Note" => ctx
+                .data::<DataLoader<NoteLoader>>()?
+                .load_one(local_id)
+                .await?
+                .map(Node::Note),
+            "Author" => ctx
+                .data::<DataLoader<AuthorLoader>>()?
+                .load_one(local_id)
+                .await?
+                .map(Node::Author),
+            _ => None,
+        })
+    }
+}
+
+/// Encodes `Type:id` as the opaque global id Relay expects.
+pub(crate) fn global_id(type_name: &str, id: &ID) -> ID {
+    ID(base64::encode(format!("{}:{}", type_name, id.as_str())))
+}
+
+/// Splits a global id into its type name and local id. Malformed base64 is an
+/// input error; anything that doesn't decode to `Type:id` is `None`.
+pub(crate) fn decode_global_id(id: &ID) -> Result<Option<(String, String)>, AppError> {
+    let bytes = base64::decode(id.as_str()).map_err(|_| AppError::InvalidInput {
+        field: Some("id"),
+        message: format!("'{}' is not a valid global id", id.as_str()),
+    })?;
+    Ok(String::from_utf8(bytes).ok().and_then(|decoded| {
+        let (type_name, local_id) = decoded.split_once(':')?;
+        Some((type_name.to_owned(), local_id.to_owned()))
+    }))
+}
+
+/// The local id inside `id` if it is a global id of `type_name`; any other id
+/// is taken to be local already.
+pub(crate) fn local_id<'a>(type_name: &str, id: &'a ID) -> Cow<'a, str> {
+    match decode_global_id(id) {
+        Ok(Some((decoded_type, local_id))) if decoded_type == type_name => Cow::Owned(local_id),
+        _ => Cow::Borrowed(id.as_str()),
+    }
+}
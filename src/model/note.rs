@@ -0,0 +1,240 @@
+use super::connection::{paginate, OffsetCursor, DEFAULT_PAGE_SIZE};
+use super::node::{global_id, local_id};
+use super::{AppError, Author, AuthorStore, MaxLength, SlugValidator};
+use crate::auth::RoleGuard;
+use crate::loaders::{AuthorLoader, NoteLoader};
+use async_graphql::connection::Connection;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{ComplexObject, Context, GuardExt, Object, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+#[derive(SimpleObject, Clone, Debug)]
+#[graphql(complex)]
+pub(crate) struct Note {
+    #[graphql(skip)]
+    pub id: ID,
+    pub title: String,
+    pub body: String,
+    pub slug: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[graphql(skip)]
+    pub author_id: Option<ID>,
+}
+
+#[ComplexObject]
+impl Note {
+    /// Global id, accepted by `Query.node` and wherever a note id is expected.
+    async fn id(&self) -> ID {
+        global_id("Note", &self.id)
+    }
+
+    /// Resolved through the schema-wide `AuthorLoader`, so listing many notes
+    /// costs one author lookup per batch.
+    async fn author(&self, ctx: &Context<'_>) -> Result<Option<Author>, AppError> {
+        match &self.author_id {
+            Some(id) => Ok(ctx
+                .data::<DataLoader<AuthorLoader>>()?
+                .load_one(id.clone())
+                .await?),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct NoteQuery;
+
+#[Object]
+impl NoteQuery {
+    #[graphql(cache_control(no_cache))]
+    async fn note(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Note>, AppError> {
+        Ok(ctx.data::<DataLoader<NoteLoader>>()?.load_one(id).await?)
+    }
+
+    /// Resolves `Note` representations for the federation gateway (`@key(fields: "id")`).
+    #[graphql(entity, cache_control(no_cache))]
+    async fn find_note_by_id(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Note>, AppError> {
+        Ok(ctx.data::<DataLoader<NoteLoader>>()?.load_one(id).await?)
+    }
+
+    /// Relay-style connection over all notes, in creation order.
+    ///
+    /// At most `DEFAULT_PAGE_SIZE` notes are returned when neither `first`
+    /// nor `last` is given.
+    #[graphql(
+        cache_control(no_cache),
+        complexity = "first.or(last).map_or(DEFAULT_PAGE_SIZE, |n| n.max(0) as usize) * child_complexity"
+    )]
+    async fn notes(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        #[graphql(validator(minimum = 0, maximum = 100))] first: Option<i32>,
+        #[graphql(validator(minimum = 0, maximum = 100))] last: Option<i32>,
+    ) -> Result<Connection<OffsetCursor, Note>, AppError> {
+        let notes = ctx.data::<NoteStore>()?.list().await;
+        paginate(notes, after, before, first, last).await
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct NoteMutation;
+
+#[Object]
+impl NoteMutation {
+    #[graphql(
+        guard = "MaxLength::new(\"title\", title.as_str(), |limits| limits.max_title_length)
+        .and(MaxLength::new(\"body\", body.as_str(), |limits| limits.max_body_length))"
+    )]
+    async fn create_note(
+        &self,
+        ctx: &Context<'_>,
+        title: String,
+        body: String,
+        author_id: Option<ID>,
+        #[graphql(validator(custom = "SlugValidator"))] slug: Option<String>,
+    ) -> Result<Note, AppError> {
+        validate_title(&title)?;
+        if let Some(author_id) = &author_id {
+            if !ctx.data::<AuthorStore>()?.contains(author_id).await {
+                return Err(AppError::NotFound {
+                    resource: "author",
+                    id: author_id.to_string(),
+                });
+            }
+        }
+        Ok(ctx
+            .data::<NoteStore>()?
+            .insert(title, body, author_id, slug)
+            .await)
+    }
+
+    #[graphql(
+        guard = "MaxLength::new(\"title\", title.as_deref(), |limits| limits.max_title_length)
+        .and(MaxLength::new(\"body\", body.as_deref(), |limits| limits.max_body_length))"
+    )]
+    async fn update_note(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        title: Option<String>,
+        body: Option<String>,
+    ) -> Result<Note, AppError> {
+        if let Some(title) = &title {
+            validate_title(title)?;
+        }
+        ctx.data::<NoteStore>()?
+            .update(&id, title, body)
+            .await
+            .ok_or_else(|| note_not_found(&id))
+    }
+
+    /// Restricted to admins.
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn delete_note(&self, ctx: &Context<'_>, id: ID) -> Result<Note, AppError> {
+        ctx.data::<NoteStore>()?
+            .remove(&id)
+            .await
+            .ok_or_else(|| note_not_found(&id))
+    }
+}
+
+/// In-memory note storage shared through the schema data.
+#[derive(Clone, Default)]
+pub(crate) struct NoteStore {
+    notes: Arc<RwLock<HashMap<u64, Note>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl NoteStore {
+    pub async fn get(&self, id: &ID) -> Option<Note> {
+        let key = parse_id(id)?;
+        self.notes.read().await.get(&key).cloned()
+    }
+
+    pub async fn get_many(&self, ids: &[ID]) -> HashMap<ID, Note> {
+        let notes = self.notes.read().await;
+        ids.iter()
+            .filter_map(|id| {
+                let note = notes.get(&parse_id(id)?)?;
+                Some((id.clone(), note.clone()))
+            })
+            .collect()
+    }
+
+    pub async fn list(&self) -> Vec<Note> {
+        let notes = self.notes.read().await;
+        let mut keys: Vec<&u64> = notes.keys().collect();
+        keys.sort();
+        keys.into_iter().map(|key| notes[key].clone()).collect()
+    }
+
+    pub async fn insert(
+        &self,
+        title: String,
+        body: String,
+        author_id: Option<ID>,
+        slug: Option<String>,
+    ) -> Note {
+        let key = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let note = Note {
+            id: ID(key.to_string()),
+            title,
+            body,
+            slug,
+            created_at: Utc::now(),
+            author_id,
+        };
+        self.notes.write().await.insert(key, note.clone());
+        note
+    }
+
+    pub async fn update(&self, id: &ID, title: Option<String>, body: Option<String>) -> Option<Note> {
+        let key = parse_id(id)?;
+        let mut notes = self.notes.write().await;
+        let note = notes.get_mut(&key)?;
+        if let Some(title) = title {
+            note.title = title;
+        }
+        if let Some(body) = body {
+            note.body = body;
+        }
+        Some(note.clone())
+    }
+
+    pub async fn remove(&self, id: &ID) -> Option<Note> {
+        let key = parse_id(id)?;
+        self.notes.write().await.remove(&key)
+    }
+    /// Waits for the storage to accept a read; a datastore-backed store
+    /// would run a round-trip query here instead.
+    pub async fn ping(&self) {
+        drop(self.notes.read().await);
+    }
+}
+
+fn parse_id(id: &ID) -> Option<u64> {
+    local_id("Note", id).parse().ok()
+}
+
+fn validate_title(title: &str) -> Result<(), AppError> {
+    if title.trim().is_empty() {
+        return Err(AppError::InvalidInput {
+            field: Some("title"),
+            message: "title must not be empty".into(),
+        });
+    }
+    Ok(())
+}
+
+pub(super) fn note_not_found(id: &ID) -> AppError {
+    AppError::NotFound {
+        resource: "note",
+        id: id.to_string(),
+    }
+}
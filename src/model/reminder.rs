@@ -0,0 +1,107 @@
+use super::connection::{paginate, OffsetCursor, DEFAULT_PAGE_SIZE};
+use super::note::note_not_found;
+use super::{AppError, NoteStore, ServiceSchemaBuilder};
+use async_graphql::connection::Connection;
+use async_graphql::{Context, InputObject, Object, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+#[derive(SimpleObject, Clone, Debug)]
+pub(crate) struct Reminder {
+    pub id: Uuid,
+    pub note_id: ID,
+    pub remind_at: DateTime<Utc>,
+}
+
+#[derive(InputObject)]
+pub(crate) struct ReminderInput {
+    /// Client-generated id, so retrying the mutation doesn't create duplicates.
+    pub id: Option<Uuid>,
+    pub note_id: ID,
+    pub remind_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub(crate) struct ReminderQuery;
+
+#[Object]
+impl ReminderQuery {
+    #[graphql(cache_control(no_cache))]
+    async fn reminder(&self, ctx: &Context<'_>, id: Uuid) -> Result<Option<Reminder>, AppError> {
+        Ok(ctx.data::<ReminderStore>()?.get(&id).await)
+    }
+
+    /// Relay-style connection over all reminders, soonest first.
+    #[graphql(
+        cache_control(no_cache),
+        complexity = "first.or(last).map_or(DEFAULT_PAGE_SIZE, |n| n.max(0) as usize) * child_complexity"
+    )]
+    async fn reminders(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        #[graphql(validator(minimum = 0, maximum = 100))] first: Option<i32>,
+        #[graphql(validator(minimum = 0, maximum = 100))] last: Option<i32>,
+    ) -> Result<Connection<OffsetCursor, Reminder>, AppError> {
+        let reminders = ctx.data::<ReminderStore>()?.list().await;
+        paginate(reminders, after, before, first, last).await
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ReminderMutation;
+
+#[Object]
+impl ReminderMutation {
+    async fn schedule_reminder(
+        &self,
+        ctx: &Context<'_>,
+        input: ReminderInput,
+    ) -> Result<Reminder, AppError> {
+        if ctx.data::<NoteStore>()?.get(&input.note_id).await.is_none() {
+            return Err(note_not_found(&input.note_id));
+        }
+        Ok(ctx.data::<ReminderStore>()?.upsert(input).await)
+    }
+}
+
+/// Adds the data the reminder resolvers read to the schema.
+pub(super) fn register(builder: ServiceSchemaBuilder) -> ServiceSchemaBuilder {
+    builder.data(ReminderStore::default())
+}
+
+/// In-memory reminder storage shared through the schema data.
+#[derive(Clone, Default)]
+pub(crate) struct ReminderStore {
+    reminders: Arc<RwLock<HashMap<Uuid, Reminder>>>,
+}
+
+impl ReminderStore {
+    pub async fn get(&self, id: &Uuid) -> Option<Reminder> {
+        self.reminders.read().await.get(id).cloned()
+    }
+
+    /// Every reminder, ordered by `remind_at` (then id) so pages are stable.
+    pub async fn list(&self) -> Vec<Reminder> {
+        let mut reminders: Vec<Reminder> = self.reminders.read().await.values().cloned().collect();
+        reminders.sort_by_key(|reminder| (reminder.remind_at, reminder.id));
+        reminders
+    }
+
+    pub async fn upsert(&self, input: ReminderInput) -> Reminder {
+        let reminder = Reminder {
+            id: input.id.unwrap_or_else(Uuid::new_v4),
+            note_id: input.note_id,
+            remind_at: input.remind_at,
+        };
+        self.reminders
+            .write()
+            .await
+            .insert(reminder.id, reminder.clone());
+        reminder
+    }
+}
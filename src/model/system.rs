@@ -0,0 +1,43 @@
+use super::{AppError, BuildInfo, ClientInfo, BUILD_INFO};
+use crate::auth::require_claims;
+use crate::context::RequestContext;
+use async_graphql::{Context, Object, ID};
+use chrono::{DateTime, Utc};
+
+/// Root fields about the service itself and its caller.
+#[derive(Default)]
+pub(crate) struct SystemQuery;
+
+/// Fields whose value changes between requests are `no_cache`: async-graphql
+/// skips fields without a `cache_control` when merging, so an unmarked one
+/// selected next to `hello` would be cached along with it.
+#[Object]
+impl SystemQuery {
+    #[graphql(cache_control(max_age = 60))]
+    async fn hello(&self, _ctx: &Context<'_>) -> &'static str {
+        "Hello World"
+    }
+
+    /// The server's current time, serialized as RFC 3339.
+    #[graphql(cache_control(no_cache))]
+    async fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    /// The authenticated caller's subject; requires a bearer token.
+    #[graphql(cache_control(no_cache))]
+    async fn me(&self, ctx: &Context<'_>) -> Result<ID, AppError> {
+        Ok(ID(require_claims(ctx)?.sub.clone()))
+    }
+
+    /// The running build; the same data `GET /version` returns.
+    async fn version(&self) -> BuildInfo {
+        BUILD_INFO
+    }
+
+    /// The calling client, as seen by the server.
+    #[graphql(cache_control(no_cache))]
+    async fn client_info(&self, ctx: &Context<'_>) -> Result<ClientInfo, AppError> {
+        Ok(ClientInfo::from(ctx.data::<RequestContext>()?))
+    }
+}
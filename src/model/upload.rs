@@ -0,0 +1,39 @@
+use super::AppError;
+use async_graphql::{Context, Object, SimpleObject, Upload};
+use sha2::{Digest, Sha256};
+use std::io;
+
+#[derive(Default)]
+pub(crate) struct UploadMutation;
+
+#[Object]
+impl UploadMutation {
+    async fn upload_file(&self, ctx: &Context<'_>, file: Upload) -> Result<UploadedFile, AppError> {
+        UploadedFile::read(ctx, file)
+    }
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct UploadedFile {
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub size: u64,
+    /// Hex-encoded SHA-256 of the file content.
+    pub sha256: String,
+}
+
+impl UploadedFile {
+    pub fn read(ctx: &Context<'_>, file: Upload) -> Result<Self, AppError> {
+        let internal = |error: io::Error| AppError::Internal(error.to_string());
+        let mut upload = file.value(ctx).map_err(internal)?;
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut upload.content, &mut hasher).map_err(internal)?;
+
+        Ok(UploadedFile {
+            filename: upload.filename,
+            content_type: upload.content_type,
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
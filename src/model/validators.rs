@@ -0,0 +1,70 @@
+use super::AppError;
+use async_graphql::{Context, CustomValidator, Guard};
+use async_trait::async_trait;
+
+/// Length limits for free-text arguments, registered as schema data so
+/// deployments can tune them without recompiling.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct InputLimits {
+    pub max_title_length: usize,
+    pub max_body_length: usize,
+}
+
+/// Accepts lowercase ASCII words separated by single hyphens, e.g.
+/// `release-notes-2`.
+pub(crate) struct SlugValidator;
+
+impl CustomValidator<String> for SlugValidator {
+    fn check(&self, value: &String) -> Result<(), String> {
+        let valid = !value.is_empty()
+            && value.split('-').all(|word| {
+                !word.is_empty()
+                    && word
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+            });
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("'{}' is not a valid slug", value))
+        }
+    }
+}
+
+/// Rejects a string argument longer than its limit in `InputLimits`. It is a
+/// guard rather than a validator because validators can't read schema data;
+/// like a validator it runs before the resolver body.
+pub(crate) struct MaxLength<'a> {
+    argument: &'static str,
+    value: Option<&'a str>,
+    limit: fn(&InputLimits) -> usize,
+}
+
+impl<'a> MaxLength<'a> {
+    pub fn new(
+        argument: &'static str,
+        value: impl Into<Option<&'a str>>,
+        limit: fn(&InputLimits) -> usize,
+    ) -> Self {
+        MaxLength {
+            argument,
+            value: value.into(),
+            limit,
+        }
+    }
+}
+
+#[async_trait]
+impl Guard for MaxLength<'_> {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let limit = (self.limit)(ctx.data::<InputLimits>()?);
+        match self.value {
+            Some(value) if value.chars().count() > limit => Err(AppError::InvalidInput {
+                field: Some(self.argument),
+                message: format!("{} must be at most {} characters", self.argument, limit),
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
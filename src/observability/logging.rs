@@ -0,0 +1,54 @@
+use crate::config::LogFormat;
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Builds the log formatting layer for `format`.
+pub(crate) fn create_fmt_layer<S>(format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    match format {
+        LogFormat::Pretty => Box::new(tracing_subscriber::fmt::layer().pretty()),
+        LogFormat::Json => Box::new(tracing_subscriber::fmt::layer().json()),
+    }
+}
+
+/// Builds the level filter from `RUST_LOG` directives. It is installed below
+/// the other layers, so it applies to both log output and exported spans.
+pub(crate) fn create_env_filter(directives: &str) -> EnvFilter {
+    EnvFilter::new(directives)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::TelemetryConfig;
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    /// Whether debug events of this crate and of hyper pass `filter`.
+    fn debug_enabled(filter: EnvFilter) -> (bool, bool) {
+        tracing::subscriber::with_default(Registry::default().with(filter), || {
+            (
+                tracing::enabled!(target: "axum_graphql::routes", Level::DEBUG),
+                tracing::enabled!(target: "hyper::proto", Level::DEBUG),
+            )
+        })
+    }
+
+    #[test]
+    fn default_filter_is_verbose_for_this_crate_only() {
+        let directives = TelemetryConfig::default().log_directives;
+        assert_eq!(debug_enabled(create_env_filter(&directives)), (true, false));
+    }
+
+    #[test]
+    fn filter_is_read_from_the_directives() {
+        assert_eq!(
+            debug_enabled(create_env_filter("warn,hyper=debug")),
+            (false, true)
+        );
+    }
+}
@@ -1,20 +1,49 @@
 use axum::{extract::MatchedPath, http::Request, middleware::Next, response::IntoResponse};
-use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use metrics_exporter_prometheus::{
+    Matcher, PrometheusBuilder, PrometheusHandle, PrometheusRecorder,
+};
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::HashSet;
+use std::sync::Mutex;
 use std::time::Instant;
 
 const REQUEST_DURATION_METRIC_NAME: &str = "http_requests_duration_seconds";
+const IN_FLIGHT_METRIC_NAME: &str = "http_requests_in_flight";
+/// Latency of one GraphQL operation, labelled by operation name and type.
+pub(crate) const OPERATION_DURATION_METRIC_NAME: &str = "graphql_operation_duration_seconds";
 
-pub(crate) fn create_prometheus_recorder() -> PrometheusHandle {
-    const EXPONENTIAL_SECONDS: &[f64] = &[
-        0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
-    ];
+/// Most distinct operation names used as metric labels; clients pick the
+/// names, so later ones are all counted as `other`.
+const MAX_OPERATION_LABELS: usize = 100;
+/// Longest operation name used as a metric label.
+const MAX_OPERATION_LABEL_LEN: usize = 64;
+
+static PROMETHEUS_HANDLE: OnceCell<PrometheusHandle> = OnceCell::new();
+static OPERATION_LABELS: Lazy<OperationLabels> = Lazy::new(OperationLabels::default);
+
+/// Installs the global Prometheus recorder on first use and returns its handle.
+/// Later calls return the same handle and ignore `buckets`.
+pub(crate) fn create_prometheus_recorder(buckets: &[f64]) -> PrometheusHandle {
+    PROMETHEUS_HANDLE
+        .get_or_init(|| install_prometheus_recorder(buckets))
+        .clone()
+}
 
+fn install_prometheus_recorder(buckets: &[f64]) -> PrometheusHandle {
+    let recorder = build_prometheus_recorder(buckets);
+    let handle = recorder.handle();
+    metrics::set_boxed_recorder(Box::new(recorder))
+        .expect("Could not install the Prometheus recorder");
+    handle
+}
+
+fn build_prometheus_recorder(buckets: &[f64]) -> PrometheusRecorder {
     PrometheusBuilder::new()
         .set_buckets_for_metric(
             Matcher::Full(
                 REQUEST_DURATION_METRIC_NAME.to_string()
             ),
-            EXPONENTIAL_SECONDS,
+            buckets,
         )
         .unwrap_or_else(|_| {
             panic!(
@@ -22,20 +51,35 @@ pub(crate) fn create_prometheus_recorder() -> PrometheusHandle {
                 REQUEST_DURATION_METRIC_NAME
             )
         })
-        .install_recorder()
-        .expect("Could not install the Prometheus recorder")
+        .set_buckets_for_metric(
+            Matcher::Full(
+                OPERATION_DURATION_METRIC_NAME.to_string()
+            ),
+            buckets,
+        )
+        .unwrap_or_else(|_| {
+            panic!(
+                "Could not initialize the bucket for '{}'",
+                OPERATION_DURATION_METRIC_NAME
+            )
+        })
+        .build_recorder()
 }
 
 pub(crate) async fn track_metrics<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
     let start = Instant::now();
+    // Label by route template; raw paths of unmatched requests would give
+    // every scanned URL its own series.
     let path = if let Some(matched_path) = req.extensions().get::<MatchedPath>() {
         matched_path.as_str().to_owned()
     } else {
-        req.uri().path().to_owned()
+        "unknown".to_owned()
     };
     let method = req.method().clone();
 
+    let in_flight = InFlight::start(path.clone());
     let response = next.run(req).await;
+    drop(in_flight);
 
     let latency = start.elapsed().as_secs_f64();
     let status = response.status().as_u16().to_string();
@@ -51,3 +95,79 @@ pub(crate) async fn track_metrics<B>(req: Request<B>, next: Next<B>) -> impl Int
 
     response
 }
+
+/// The `operation` label for an operation named `name`: the name itself for
+/// the first `MAX_OPERATION_LABELS` names seen, `other` after that or when it
+/// is too long.
+pub(crate) fn operation_label(name: &str) -> String {
+    OPERATION_LABELS.label(name, MAX_OPERATION_LABELS)
+}
+
+/// Operation names already used as labels.
+#[derive(Default)]
+struct OperationLabels(Mutex<HashSet<String>>);
+
+impl OperationLabels {
+    fn label(&self, name: &str, max: usize) -> String {
+        if name.len() > MAX_OPERATION_LABEL_LEN {
+            return "other".to_owned();
+        }
+        let mut names = self.0.lock().unwrap();
+        if !names.contains(name) {
+            if names.len() >= max {
+                return "other".to_owned();
+            }
+            names.insert(name.to_owned());
+        }
+        name.to_owned()
+    }
+}
+
+/// Counts a request in `http_requests_in_flight` until dropped, so requests
+/// that are cancelled or panic downstream are still counted out.
+struct InFlight {
+    path: String,
+}
+
+impl InFlight {
+    fn start(path: String) -> Self {
+        metrics::increment_gauge!(IN_FLIGHT_METRIC_NAME, 1.0, "path" => path.clone());
+        InFlight { path }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        metrics::decrement_gauge!(IN_FLIGHT_METRIC_NAME, 1.0, "path" => self.path.clone());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use metrics::{Key, Recorder};
+
+    #[test]
+    fn recorder_uses_the_given_buckets() {
+        let recorder = build_prometheus_recorder(&[0.25, 0.75]);
+        recorder
+            .register_histogram(&Key::from_name(REQUEST_DURATION_METRIC_NAME))
+            .record(0.5);
+
+        let output = recorder.handle().render();
+        assert!(output.contains(r#"http_requests_duration_seconds_bucket{le="0.25"} 0"#));
+        assert!(output.contains(r#"http_requests_duration_seconds_bucket{le="0.75"} 1"#));
+        assert!(!output.contains(r#"le="0.005""#));
+    }
+
+    #[test]
+    fn operation_labels_are_capped() {
+        let labels = OperationLabels::default();
+
+        assert_eq!(labels.label("First", 2), "First");
+        assert_eq!(labels.label("Second", 2), "Second");
+        assert_eq!(labels.label("Third", 2), "other");
+        assert_eq!(labels.label("First", 2), "First");
+        assert_eq!(labels.label(&"A".repeat(65), 2), "other");
+    }
+}
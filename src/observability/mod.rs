@@ -1,2 +1,3 @@
+pub(crate) mod logging;
 pub(crate) mod metrics;
 pub(crate) mod tracing;
\ No newline at end of file
@@ -1,45 +1,77 @@
+use crate::config::{TelemetryConfig, TraceExporter};
 use opentelemetry::sdk::trace::{self, Sampler};
+use opentelemetry::sdk::Resource;
 use opentelemetry::{
     global, runtime::Tokio, sdk::propagation::TraceContextPropagator, sdk::trace::Tracer,
+    KeyValue,
 };
-use std::env;
-struct JaegerConfig {
-    jaeger_agent_host: String,
-    jaeger_agent_port: String,
-    jaeger_tracing_service_name: String,
-}
-pub fn create_tracer_from_env() -> Option<Tracer> {
-    let jaeger_enabled: bool = env::var("JAEGER_ENABLED")
-        .unwrap_or_else(|_| "false".into())
-        .parse()
-        .unwrap();
-    if jaeger_enabled {
-        let config = get_jaeger_config_from_env();
-        Some(init_tracer(config))
-    } else {
-        None
+use opentelemetry_otlp::WithExportConfig;
+/// Builds the tracer for the configured exporter; `None` when spans aren't
+/// exported.
+pub fn create_tracer(config: &TelemetryConfig) -> Option<Tracer> {
+    let sampler = sampler(config.sample_ratio);
+    match config.exporter.as_ref()? {
+        TraceExporter::Jaeger { agent_endpoint } => {
+            Some(init_tracer(agent_endpoint, &config.service_name, sampler))
+        }
+        TraceExporter::Otlp { endpoint } => {
+            Some(init_otlp_tracer(endpoint, &config.service_name, sampler))
+        }
     }
 }
-fn init_tracer(config: JaegerConfig) -> Tracer {
+fn init_tracer(agent_endpoint: &str, service_name: &str, sampler: Sampler) -> Tracer {
     global::set_text_map_propagator(TraceContextPropagator::new());
     opentelemetry_jaeger::new_agent_pipeline() 
-        .with_endpoint(format!(
-            "{}:{}",
-            config.jaeger_agent_host, config.jaeger_agent_port
-        ))
+        .with_endpoint(agent_endpoint)
         .with_auto_split_batch(true)
-        .with_service_name(config.jaeger_tracing_service_name)
-        .with_trace_config(trace::config().with_sampler(Sampler::AlwaysOn))
+        .with_service_name(service_name)
+        .with_trace_config(trace::config().with_sampler(sampler))
+        .install_batch(Tokio)
+        .expect("pipeline install error")
+}
+fn init_otlp_tracer(endpoint: &str, service_name: &str, sampler: Sampler) -> Tracer {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_sampler(sampler)
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    service_name.to_string(),
+                )])),
+        )
         .install_batch(Tokio)
         .expect("pipeline install error")
 }
-fn get_jaeger_config_from_env() -> JaegerConfig {
-    JaegerConfig {
-        jaeger_agent_host: env::var("JAEGER_AGENT_HOST")
-            .unwrap_or_else(|_| "localhost".into()),
-        jaeger_agent_port: env::var("JAEGER_AGENT_PORT")
-            .unwrap_or_else(|_| "6831".into()),
-        jaeger_tracing_service_name: env::var("TRACING_SERVICE_NAME")
-            .unwrap_or_else(|_| "axum-graphql".into()),
+/// Samples every trace unless a ratio is set; spans with a parent keep the
+/// parent's decision either way.
+fn sampler(ratio: Option<f64>) -> Sampler {
+    match ratio {
+        Some(ratio) => Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio))),
+        None => Sampler::AlwaysOn,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sampler_defaults_to_always_on() {
+        assert_eq!(format!("{:?}", sampler(None)), "AlwaysOn");
+    }
+
+    #[test]
+    fn sampler_ratio_follows_the_parent() {
+        assert_eq!(
+            format!("{:?}", sampler(Some(0.25))),
+            "ParentBased(TraceIdRatioBased(0.25))"
+        );
+    }
+}
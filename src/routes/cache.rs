@@ -0,0 +1,148 @@
+use super::operation_type;
+use async_graphql::parser::types::OperationType;
+use async_graphql::{CacheControl, Request, Response, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most responses held at once; fresh responses are not cached beyond it.
+const MAX_ENTRIES: usize = 1000;
+
+/// In-process cache of query responses, kept for the `max_age` async-graphql
+/// derives from the `cache_control` of the selected fields. Fields without
+/// one don't count in that merge, so fields whose value changes per request
+/// must be marked `no_cache` to keep a response that selects them out.
+///
+/// Only anonymous requests go through it, so a response never depends on who
+/// asked for it.
+#[derive(Clone, Default)]
+pub(crate) struct ResponseCache {
+    entries: Arc<Mutex<HashMap<CacheKey, CachedResponse>>>,
+    max_ttl: Option<Duration>,
+}
+
+#[derive(Hash, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+    query: String,
+    operation_name: Option<String>,
+    variables: String,
+}
+
+struct CachedResponse {
+    expires_at: Instant,
+    data: Value,
+    cache_control: CacheControl,
+}
+
+impl ResponseCache {
+    /// A cache that keeps no response longer than `max_ttl`, when set.
+    pub fn new(max_ttl: Option<Duration>) -> Self {
+        Self {
+            max_ttl,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the key `request` is cached under, or `None` unless it is a
+    /// query sent with its document.
+    pub fn key(request: &Request) -> Option<CacheKey> {
+        if request.query.is_empty() || operation_type(request) != Some(OperationType::Query) {
+            return None;
+        }
+        Some(CacheKey {
+            query: request
+                .query
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+            operation_name: request.operation_name.clone(),
+            variables: serde_json::to_string(&request.variables).unwrap_or_default(),
+        })
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Response> {
+        let entries = self.entries.lock().unwrap();
+        let response = entries
+            .get(key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| {
+                let mut response = Response::new(entry.data.clone());
+                response.cache_control = entry.cache_control.clone();
+                response
+            });
+        let result = if response.is_some() { "hit" } else { "miss" };
+        metrics::increment_counter!("graphql_response_cache_lookups_total", "result" => result);
+        response
+    }
+
+    /// Stores `response` if it succeeded and its cache control allows sharing.
+    pub fn insert(&self, key: CacheKey, response: &Response) {
+        let cache_control = &response.cache_control;
+        if !response.errors.is_empty() || !cache_control.public || cache_control.max_age <= 0 {
+            return;
+        }
+        let ttl = Duration::from_secs(cache_control.max_age as u64);
+        let ttl = self.max_ttl.map_or(ttl, |max_ttl| ttl.min(max_ttl));
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.expires_at > now);
+        if entries.len() >= MAX_ENTRIES {
+            return;
+        }
+        entries.insert(
+            key,
+            CachedResponse {
+                expires_at: now + ttl,
+                data: response.data.clone(),
+                cache_control: cache_control.clone(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cacheable(max_age: i32) -> Response {
+        let mut response = Response::new(Value::from("Hello World"));
+        response.cache_control = CacheControl {
+            public: true,
+            max_age,
+        };
+        response
+    }
+
+    #[test]
+    fn fresh_responses_are_reused() {
+        let cache = ResponseCache::default();
+        let key = || ResponseCache::key(&Request::new("{ hello }")).unwrap();
+
+        assert!(cache.get(&key()).is_none());
+        cache.insert(key(), &cacheable(60));
+        let cached = cache.get(&key()).unwrap();
+        assert_eq!(cached.data, Value::from("Hello World"));
+        assert_eq!(cached.cache_control.max_age, 60);
+    }
+
+    #[test]
+    fn uncacheable_responses_are_not_stored() {
+        let cache = ResponseCache::default();
+        let key = || ResponseCache::key(&Request::new("{ hello now }")).unwrap();
+
+        // What `no_cache` fields merge to.
+        cache.insert(key(), &cacheable(-1));
+        assert!(cache.get(&key()).is_none());
+    }
+
+    #[test]
+    fn max_ttl_caps_the_max_age() {
+        let cache = ResponseCache::new(Some(Duration::from_millis(20)));
+        let key = || ResponseCache::key(&Request::new("{ hello }")).unwrap();
+
+        cache.insert(key(), &cacheable(60));
+        assert!(cache.get(&key()).is_some());
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(cache.get(&key()).is_none());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::layers::request_id::RequestId;
+use axum::{
+    extract::Extension,
+    http::{header, Request, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+/// JSON body for requests that don't reach a handler.
+#[derive(Serialize)]
+pub(crate) struct RouteError {
+    #[serde(skip)]
+    status: StatusCode,
+    error: &'static str,
+    path: String,
+    /// The methods the path does accept, for `405`s.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    allowed: Vec<String>,
+    /// Lets a client report point at the server's log lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl RouteError {
+    pub fn not_found(path: &str) -> Self {
+        RouteError {
+            status: StatusCode::NOT_FOUND,
+            error: "not_found",
+            path: path.to_owned(),
+            allowed: Vec::new(),
+            request_id: None,
+        }
+    }
+
+    pub fn method_not_allowed(path: &str, allowed: Vec<String>) -> Self {
+        RouteError {
+            status: StatusCode::METHOD_NOT_ALLOWED,
+            error: "method_not_allowed",
+            path: path.to_owned(),
+            allowed,
+            request_id: None,
+        }
+    }
+
+    pub fn request_id(mut self, request_id: Option<RequestId>) -> Self {
+        self.request_id = request_id.map(|RequestId(id)| id);
+        self
+    }
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+/// Fallback for paths no route matches.
+pub(crate) async fn not_found(uri: Uri, request_id: Option<Extension<RequestId>>) -> RouteError {
+    RouteError::not_found(uri.path()).request_id(request_id.map(|Extension(id)| id))
+}
+
+/// Gives the empty `405` axum answers for a known path with the wrong method
+/// a `RouteError` body listing its `Allow` header, which is kept.
+pub(crate) async fn method_not_allowed<B>(req: Request<B>, next: Next<B>) -> Response {
+    let path = req.uri().path().to_owned();
+    let request_id = req.extensions().get::<RequestId>().cloned();
+    let response = next.run(req).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED
+        || response.headers().contains_key(header::CONTENT_TYPE)
+    {
+        return response;
+    }
+
+    let (mut parts, _) = response.into_parts();
+    let allowed = parts
+        .headers
+        .get(header::ALLOW)
+        .and_then(|allow| allow.to_str().ok())
+        .map(|allow| {
+            allow
+                .split(',')
+                .map(|method| method.trim().to_owned())
+                .filter(|method| !method.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let (json_parts, body) = RouteError::method_not_allowed(&path, allowed)
+        .request_id(request_id)
+        .into_response()
+        .into_parts();
+    parts.headers.extend(json_parts.headers);
+    Response::from_parts(parts, body)
+}
@@ -0,0 +1,292 @@
+use crate::config::HealthFormat;
+use crate::model::{NoteStore, ServiceSchema};
+use crate::shutdown::{Phase, ShutdownSignal};
+use async_trait::async_trait;
+use axum::{
+    extract::Extension,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+const STORE_PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Health {
+    /// `false` when any required component is down.
+    pub healthy: bool,
+    /// `shutting_down` once the shutdown signal has arrived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct ComponentHealth {
+    pub status: ComponentStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ComponentStatus {
+    Up,
+    Down,
+}
+
+/// `/health` under `HEALTH_FORMAT=rfc`, after the draft health-check RFC.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct RfcHealth {
+    pub status: RfcStatus,
+    /// One single-entry list per component, as the draft nests them.
+    pub checks: BTreeMap<String, Vec<RfcCheck>>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct RfcCheck {
+    pub status: RfcStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RfcStatus {
+    Pass,
+    Fail,
+}
+
+impl From<bool> for RfcStatus {
+    fn from(passed: bool) -> Self {
+        if passed {
+            RfcStatus::Pass
+        } else {
+            RfcStatus::Fail
+        }
+    }
+}
+
+impl From<Health> for RfcHealth {
+    fn from(health: Health) -> Self {
+        let checks = health
+            .components
+            .into_iter()
+            .map(|(name, component)| {
+                let check = RfcCheck {
+                    status: (component.status == ComponentStatus::Up).into(),
+                    output: component.message,
+                };
+                (name, vec![check])
+            })
+            .collect();
+        RfcHealth {
+            status: health.healthy.into(),
+            checks,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Liveness {
+    pub alive: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Readiness {
+    pub ready: bool,
+    /// Status of every required check, by name.
+    pub checks: BTreeMap<String, ComponentStatus>,
+    pub failing: Vec<FailedCheck>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct FailedCheck {
+    pub name: String,
+    pub error: String,
+}
+
+/// A dependency the service reports on; registered in `create_app`.
+#[async_trait]
+pub(crate) trait HealthCheck: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether the service is unhealthy (and not ready) while this fails.
+    fn required(&self) -> bool {
+        true
+    }
+
+    async fn check(&self) -> Result<(), String>;
+}
+
+/// Checks that the schema describes its query root.
+///
+/// This reads the registry rather than executing a query, because execution
+/// goes through the request extensions, and an allow-list without
+/// introspection would reject any probe query. The schema never changes once
+/// built, so the SDL is rendered once here instead of on every probe.
+pub(crate) struct SchemaCheck(Result<(), String>);
+
+impl SchemaCheck {
+    pub fn new(schema: &ServiceSchema) -> Self {
+        if schema.sdl().contains("type QueryRoot") {
+            SchemaCheck(Ok(()))
+        } else {
+            SchemaCheck(Err("query root is missing from the schema".into()))
+        }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for SchemaCheck {
+    fn name(&self) -> &'static str {
+        "schema"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.0.clone()
+    }
+}
+
+/// Checks that the note store answers within `STORE_PING_TIMEOUT`.
+pub(crate) struct StoreCheck(pub NoteStore);
+
+#[async_trait]
+impl HealthCheck for StoreCheck {
+    fn name(&self) -> &'static str {
+        "store"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        tokio::time::timeout(STORE_PING_TIMEOUT, self.0.ping())
+            .await
+            .map_err(|_| format!("no answer within {:?}", STORE_PING_TIMEOUT))
+    }
+}
+
+/// Fails until `main` marks the server ready and again once shutdown starts,
+/// so load balancers stop routing to it before connections are closed.
+pub(crate) struct LifecycleCheck(pub ShutdownSignal);
+
+#[async_trait]
+impl HealthCheck for LifecycleCheck {
+    fn name(&self) -> &'static str {
+        "lifecycle"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        match self.0.phase() {
+            Phase::Starting => Err("starting".into()),
+            Phase::Ready => Ok(()),
+            Phase::ShuttingDown => Err("shutting down".into()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct HealthChecks(Arc<Vec<Box<dyn HealthCheck>>>);
+
+impl HealthChecks {
+    pub fn new(checks: Vec<Box<dyn HealthCheck>>) -> Self {
+        HealthChecks(Arc::new(checks))
+    }
+
+    /// Runs every check concurrently.
+    async fn run(&self) -> Vec<(&dyn HealthCheck, Result<(), String>)> {
+        join_all(self.0.iter().map(|check| async move {
+            let check = check.as_ref();
+            (check, check.check().await)
+        }))
+        .await
+    }
+}
+
+/// Answers as long as the runtime can run a handler; checks nothing else.
+pub(crate) async fn liveness() -> impl IntoResponse {
+    Json(Liveness { alive: true })
+}
+
+/// Runs every check and reports each component, in the `HealthFormat`
+/// registered as a request extension (`Native` when there is none). Once the
+/// registered `ShutdownSignal` has fired it also says it is shutting down.
+pub(crate) async fn health(
+    Extension(checks): Extension<HealthChecks>,
+    format: Option<Extension<HealthFormat>>,
+    shutdown: Option<Extension<ShutdownSignal>>,
+) -> Response {
+    let mut healthy = true;
+    let mut components = BTreeMap::new();
+    for (check, result) in checks.run().await {
+        healthy &= result.is_ok() || !check.required();
+        let component = match result {
+            Ok(()) => ComponentHealth {
+                status: ComponentStatus::Up,
+                message: None,
+            },
+            Err(message) => ComponentHealth {
+                status: ComponentStatus::Down,
+                message: Some(message),
+            },
+        };
+        components.insert(check.name().to_owned(), component);
+    }
+
+    let shutting_down = shutdown.map_or(false, |Extension(shutdown)| shutdown.is_triggered());
+    healthy &= !shutting_down;
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let health = Health {
+        healthy,
+        reason: shutting_down.then(|| "shutting_down".to_owned()),
+        components,
+    };
+    match format {
+        Some(Extension(HealthFormat::Rfc)) => (
+            status,
+            [(header::CONTENT_TYPE, "application/health+json")],
+            Json(RfcHealth::from(health)),
+        )
+            .into_response(),
+        _ => (status, Json(health)).into_response(),
+    }
+}
+
+pub(crate) async fn readiness(Extension(checks): Extension<HealthChecks>) -> impl IntoResponse {
+    let mut statuses = BTreeMap::new();
+    let mut failing = Vec::new();
+    for (check, result) in checks.run().await {
+        if !check.required() {
+            continue;
+        }
+        let status = match result {
+            Ok(()) => ComponentStatus::Up,
+            Err(error) => {
+                failing.push(FailedCheck {
+                    name: check.name().to_owned(),
+                    error,
+                });
+                ComponentStatus::Down
+            }
+        };
+        statuses.insert(check.name().to_owned(), status);
+    }
+    let status = if failing.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let readiness = Readiness {
+        ready: failing.is_empty(),
+        checks: statuses,
+        failing,
+    };
+    (status, Json(readiness))
+}
@@ -1,55 +1,511 @@
-use crate::model::ServiceSchema;
-use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
-use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use crate::auth::{Authentication, ConnectionAuth};
+use crate::config::GraphQLIde;
+use crate::context::RequestContext;
+use crate::extensions::apq;
+use crate::layers::request_id::RequestId;
+use crate::loaders::NoteLoader;
+use crate::model::{BuildInfo, NoteStore, ServiceSchema, BUILD_INFO};
+use crate::observability::metrics::{operation_label, OPERATION_DURATION_METRIC_NAME};
+use crate::shutdown::ShutdownSignal;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::parser::{
+    parse_query,
+    types::{DocumentOperations, OperationType},
+};
+use async_graphql::{BatchRequest, BatchResponse, Data, SDLExportOptions, ServerError};
+use async_graphql::http::{
+    playground_source, GraphQLPlaygroundConfig, GraphiQLSource, ALL_WEBSOCKET_PROTOCOLS,
+};
+use async_graphql_axum::{GraphQLProtocol, GraphQLResponse, GraphQLWebSocket};
 use axum::{
-    extract::Extension,
-    http::StatusCode,
-    response::{Html, IntoResponse},
-    Json
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocketUpgrade},
+        Extension, RawQuery,
+    },
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
 };
-use serde::{Serialize, Deserialize};
+use futures_util::{future, SinkExt, StreamExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use opentelemetry::trace::TraceContextExt;
-use tracing::{info, span, Instrument, Level};
+use tracing::{field, info, span, Instrument, Level, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)] 
-pub(crate) struct Health {
-    pub healthy: bool
+mod cache;
+mod error;
+pub(crate) mod health;
+mod request;
+mod ws;
+
+use error::RouteError;
+use request::{BatchRequestRejection, GraphQLBatchRequest};
+use ws::ActiveOperations;
+
+pub(crate) use request::{BatchLimit, BodyLimit, ExecutionTimeout, UploadLimits};
+
+pub(crate) use cache::ResponseCache;
+pub(crate) use error::{method_not_allowed, not_found};
+pub(crate) use ws::WsLimits;
+pub(crate) use health::{
+    health, liveness, readiness, HealthChecks, LifecycleCheck, SchemaCheck, StoreCheck,
+};
+
+const TRACE_ID_HEADER: &str = "x-trace-id";
+
+/// Which IDE `GET /` serves and where it finds the endpoints; registered as
+/// a request extension.
+#[derive(Clone)]
+pub(crate) struct IdeSettings {
+    pub ide: GraphQLIde,
+    /// Prefix of the public URLs, e.g. `/api` behind a proxy; empty at the root.
+    pub base_path: Arc<str>,
+}
+
+/// Renders the configured IDE, or `404` when it is turned off.
+async fn graphql_playground(settings: &IdeSettings, request_id: Option<RequestId>) -> Response {
+    let endpoint = format!("{}/", settings.base_path);
+    let subscription_endpoint = format!("{}/ws", settings.base_path);
+    match settings.ide {
+        GraphQLIde::Playground => Html(playground_source(
+            GraphQLPlaygroundConfig::new(&endpoint).subscription_endpoint(&subscription_endpoint),
+        ))
+        .into_response(),
+        GraphQLIde::GraphiQL => Html(
+            GraphiQLSource::build()
+                .endpoint(&endpoint)
+                .subscription_endpoint(&subscription_endpoint)
+                .finish(),
+        )
+        .into_response(),
+        GraphQLIde::None => RouteError::not_found("/")
+            .request_id(request_id)
+            .into_response(),
+    }
+}
+
+/// Serves the IDE for a plain `GET /` and executes the operation for
+/// `GET /?query=...` (or a persisted query sent through `extensions`).
+///
+/// The IDE stays on `/` so the endpoint it talks to is the one a browser
+/// opens; a request counts as an operation once it carries a `query` or
+/// `extensions` parameter.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn graphql_get(
+    RawQuery(raw_query): RawQuery,
+    Extension(ide): Extension<IdeSettings>,
+    req: Result<GraphQLBatchRequest, BatchRequestRejection>,
+    context: RequestContext,
+    schema: Extension<ServiceSchema>,
+    store: Extension<NoteStore>,
+    limit: Extension<BatchLimit>,
+    timeout: Extension<ExecutionTimeout>,
+    cache: Option<Extension<ResponseCache>>,
+) -> Response {
+    if !is_operation(raw_query.as_deref()) {
+        return graphql_playground(&ide, context.request_id).await;
+    }
+    match req {
+        Ok(req) => graphql_get_query(req, context, schema, store, limit, timeout, cache).await,
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Whether a `GET /` with this query string runs an operation rather than
+/// asking for the IDE.
+pub(crate) fn is_operation(raw_query: Option<&str>) -> bool {
+    raw_query.map_or(false, |raw_query| {
+        raw_query
+            .split('&')
+            .filter_map(|pair| pair.split('=').next())
+            .any(|key| key == "query" || key == "extensions")
+    })
+}
+
+/// Executes `GET /?query=...&variables=...&operationName=...`.
+///
+/// GET responses may be cached along the way, so mutations are refused with
+/// `405` and have to be sent with POST.
+pub(crate) async fn graphql_get_query(
+    req: GraphQLBatchRequest,
+    context: RequestContext,
+    schema: Extension<ServiceSchema>,
+    store: Extension<NoteStore>,
+    limit: Extension<BatchLimit>,
+    timeout: Extension<ExecutionTimeout>,
+    cache: Option<Extension<ResponseCache>>,
+) -> Response {
+    if let BatchRequest::Single(request) = &req.0 {
+        if is_mutation(request) {
+            let response = async_graphql::Response::from_errors(vec![ServerError::new(
+                "mutations must be sent with POST",
+                None,
+            )]);
+            return (
+                StatusCode::METHOD_NOT_ALLOWED,
+                [(header::ALLOW, "POST")],
+                GraphQLResponse::from(response),
+            )
+                .into_response();
+        }
+    }
+    graphql_handler(req, context, schema, store, limit, timeout, cache).await
+}
+
+/// Whether the operation `request` selects is a mutation.
+fn is_mutation(request: &async_graphql::Request) -> bool {
+    operation_type(request) == Some(OperationType::Mutation)
+}
+
+/// The type of the operation `request` selects.
+fn operation_type(request: &async_graphql::Request) -> Option<OperationType> {
+    selected_operation(request).map(|(ty, _)| ty)
+}
+
+/// The type and name of the operation `request` selects. Queries that don't
+/// parse are left for the schema to reject.
+fn selected_operation(request: &async_graphql::Request) -> Option<(OperationType, Option<String>)> {
+    let document = parse_query(&request.query).ok()?;
+    match (document.operations, request.operation_name.as_deref()) {
+        (DocumentOperations::Single(operation), _) => Some((operation.node.ty, None)),
+        (DocumentOperations::Multiple(operations), Some(name)) => operations
+            .get(name)
+            .map(|operation| (operation.node.ty, Some(name.to_owned()))),
+        (DocumentOperations::Multiple(operations), None) if operations.len() == 1 => operations
+            .into_iter()
+            .next()
+            .map(|(name, operation)| (operation.node.ty, Some(name.to_string()))),
+        _ => None,
+    }
+}
+
+/// Records what a request runs on the `graphql_execution` span, naming the
+/// span `query GetNotes` (or `batch`) for OpenTelemetry.
+fn record_operation(span: &Span, request: &BatchRequest) {
+    match request {
+        BatchRequest::Single(request) => {
+            let (ty, name) = operation_labels(request);
+            span.record("otel.name", format!("{} {}", ty, name).as_str());
+            span.record("graphql.operation.name", name.as_str());
+            span.record("graphql.operation.type", ty);
+            span.record("graphql.document.length", request.query.len());
+            span.record("graphql.batch", false);
+        }
+        BatchRequest::Batch(requests) => {
+            let length: usize = requests.iter().map(|request| request.query.len()).sum();
+            span.record("otel.name", "batch");
+            span.record("graphql.document.length", length);
+            span.record("graphql.batch", true);
+        }
+    }
 }
 
-pub(crate) async fn health() -> impl IntoResponse {
-    let health = Health {
-        healthy: true
+/// The type and name (`anonymous` when unnamed) of the operation `request`
+/// selects, as used for span fields; metrics cap the name with
+/// `operation_label`.
+fn operation_labels(request: &async_graphql::Request) -> (&'static str, String) {
+    let (ty, name) = match selected_operation(request) {
+        Some((ty, name)) => (operation_type_name(ty), name),
+        None => ("unknown", None),
     };
-    (StatusCode::OK, Json(health))
+    (ty, name.unwrap_or_else(|| "anonymous".to_owned()))
 }
 
-pub(crate) async fn graphql_playground() -> impl IntoResponse {
-    Html(playground_source(
-        GraphQLPlaygroundConfig::new("/").subscription_endpoint("ws"),
-    ))
+fn operation_type_name(ty: OperationType) -> &'static str {
+    match ty {
+        OperationType::Query => "query",
+        OperationType::Mutation => "mutation",
+        OperationType::Subscription => "subscription",
+    }
+}
+
+pub(crate) async fn graphql_schema(
+    Extension(schema): Extension<ServiceSchema>,
+    Extension(options): Extension<SDLExportOptions>,
+) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        schema.sdl_with_options(options),
+    )
+}
+
+/// Reports the running build. Public, like the health endpoints.
+pub(crate) async fn version() -> Json<BuildInfo> {
+    Json(BUILD_INFO)
 }
 
 pub(crate) async fn graphql_handler(
-    req: GraphQLRequest,
+    req: GraphQLBatchRequest,
+    context: RequestContext,
     Extension(schema): Extension<ServiceSchema>,
-) -> GraphQLResponse {
-    let span = span!(Level::INFO, "graphql_execution");
-    let response = async move {
-        schema.execute(req.into_inner()).await
+    Extension(store): Extension<NoteStore>,
+    Extension(BatchLimit(limit)): Extension<BatchLimit>,
+    Extension(ExecutionTimeout(timeout)): Extension<ExecutionTimeout>,
+    cache: Option<Extension<ResponseCache>>,
+) -> Response {
+    let cache = cache.as_ref().map(|Extension(cache)| cache);
+    let span = span!(
+        Level::INFO,
+        "graphql_execution",
+        otel.name = field::Empty,
+        graphql.operation.name = field::Empty,
+        graphql.operation.type = field::Empty,
+        graphql.document.length = field::Empty,
+        graphql.batch = field::Empty,
+        request_id = field::Empty,
+        rejected = field::Empty,
+        timed_out = field::Empty
+    );
+    if let Some(parent) = &context.trace_parent {
+        span.set_parent(parent.clone());
+    }
+    if let Some(RequestId(id)) = &context.request_id {
+        span.record("request_id", id.as_str());
+    }
+    record_operation(&span, &req.0);
+    let trace_id = trace_id(&span);
+    let execution_span = span.clone();
+    let request_id = context.request_id.clone();
+    let execution = async move {
+        match req.into_inner() {
+            BatchRequest::Single(request) => {
+                BatchResponse::Single(execute_operation(&schema, &store, &context, cache, request).await)
+            }
+            BatchRequest::Batch(requests) => match limit {
+                Some(limit) if requests.len() > limit => {
+                    BatchResponse::Single(async_graphql::Response::from_errors(vec![
+                        ServerError::new(
+                            format!(
+                                "batch of {} operations exceeds the limit of {}",
+                                requests.len(),
+                                limit
+                            ),
+                            None,
+                        ),
+                    ]))
+                }
+                _ => {
+                    let mut responses = Vec::with_capacity(requests.len());
+                    for request in requests {
+                        responses.push(execute_operation(&schema, &store, &context, cache, request).await);
+                    }
+                    BatchResponse::Batch(responses)
+                }
+            },
+        }
     }
-    .instrument(span.clone())
-    .await;
+    .instrument(execution_span);
+    let response = match tokio::time::timeout(timeout, execution).await {
+        Ok(response) => response,
+        Err(_) => {
+            span.record("timed_out", true);
+            metrics::increment_counter!("graphql_timeouts_total");
+            BatchResponse::Single(timed_out(timeout, &trace_id, request_id))
+        }
+    };
     info!("Processing GraphQL request finished");
-    response
-        .extension(
-            "traceId"
-            , async_graphql::Value::String(format!(
-                "{}",
-                span.context().span().span_context().trace_id()
-            )),
-        )
-        .into()
+    // Also sent as a header for clients and proxies that don't read the body.
+    ([(TRACE_ID_HEADER, trace_id)], GraphQLResponse::from(response)).into_response()
+}
 
-}
\ No newline at end of file
+/// Executes one operation of a (possibly batched) request in its own
+/// `graphql_operation` span under `graphql_execution`, tagging the response
+/// with the shared trace id. Anonymous queries are answered from `cache` while
+/// a fresh response is held.
+async fn execute_operation(
+    schema: &ServiceSchema,
+    store: &NoteStore,
+    context: &RequestContext,
+    cache: Option<&ResponseCache>,
+    request: async_graphql::Request,
+) -> async_graphql::Response {
+    let span = Span::current();
+    let start = Instant::now();
+    let (operation_type, operation_name) = operation_labels(&request);
+    // Clients choose the name, so only the span gets it as sent.
+    let operation_label = operation_label(&operation_name);
+    let cache_key = match (cache, &context.auth) {
+        (Some(_), Authentication::Anonymous) => ResponseCache::key(&request),
+        _ => None,
+    };
+    let loader = DataLoader::new(NoteLoader::new(store.clone()), tokio::spawn);
+    let request = request
+        .data(loader)
+        .data(context.auth.clone())
+        .data(context.clone());
+    let operation_span = span!(
+        Level::INFO,
+        "graphql_operation",
+        operation_name = operation_name.as_str()
+    );
+    let cached = cache
+        .zip(cache_key.as_ref())
+        .and_then(|(cache, key)| cache.get(key));
+    let mut response = match (apq::verify_persisted_query(&request), cached) {
+        (Err(error), _) => async_graphql::Response::from_errors(vec![error]),
+        (Ok(()), Some(response)) => response,
+        (Ok(()), None) => {
+            let response = schema.execute(request).instrument(operation_span).await;
+            if let (Some(cache), Some(key)) = (cache, cache_key) {
+                cache.insert(key, &response);
+            }
+            response
+        }
+    };
+    apq::tag_not_found(&mut response);
+    metrics::histogram!(
+        OPERATION_DURATION_METRIC_NAME,
+        start.elapsed().as_secs_f64(),
+        "operation" => operation_label.clone(),
+        "type" => operation_type
+    );
+    // Errors come back with `200`, so the HTTP metrics don't see them.
+    if response.is_err() {
+        metrics::increment_counter!("graphql_errors_total", "operation" => operation_label);
+    }
+    if let Some(reason) = rejection_reason(&response) {
+        span.record("rejected", reason);
+        metrics::increment_counter!("graphql_rejected_requests_total", "reason" => reason);
+    }
+    let response = response.extension("traceId", async_graphql::Value::String(trace_id(&span)));
+    match &context.request_id {
+        Some(RequestId(id)) => response.extension("requestId", async_graphql::Value::String(id.clone())),
+        None => response,
+    }
+}
+
+/// The response for a request that ran past `timeout`; whatever the
+/// operations had resolved so far is dropped.
+fn timed_out(
+    timeout: Duration,
+    trace_id: &str,
+    request_id: Option<RequestId>,
+) -> async_graphql::Response {
+    let mut error = ServerError::new(format!("request timed out after {:?}", timeout), None);
+    error
+        .extensions
+        .get_or_insert_with(Default::default)
+        .set("code", "TIMEOUT");
+    let response = async_graphql::Response::from_errors(vec![error])
+        .extension("traceId", async_graphql::Value::String(trace_id.to_owned()));
+    match request_id {
+        Some(RequestId(id)) => response.extension("requestId", async_graphql::Value::String(id)),
+        None => response,
+    }
+}
+
+/// The OpenTelemetry trace id of `span`, as lowercase hex.
+fn trace_id(span: &Span) -> String {
+    span.context().span().span_context().trace_id().to_string()
+}
+
+fn close(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+/// Returns why the schema refused to execute the request, if it did.
+fn rejection_reason(response: &async_graphql::Response) -> Option<&'static str> {
+    response.errors.iter().find_map(|error| {
+        match error.extensions.as_ref()?.get("code")? {
+            async_graphql::Value::String(code) => match code.as_str() {
+                "QUERY_TOO_DEEP" => Some("depth"),
+                "QUERY_TOO_COMPLEX" => Some("complexity"),
+                _ => None,
+            },
+            _ => None,
+        }
+    })
+}
+
+/// Serves subscriptions over WebSocket, within the `WsLimits` registered as a
+/// request extension (unlimited when there are none). A connection past the
+/// cap is closed right after the upgrade, and one that starts more operations
+/// than allowed is closed when it does. `ConnectionAuth` decides who the
+/// connection belongs to once `connection_init` arrives; its operations then
+/// get the same data as an HTTP request's, with one loader per connection.
+pub(crate) async fn graphql_subscription(
+    Extension(schema): Extension<ServiceSchema>,
+    Extension(store): Extension<NoteStore>,
+    Extension(shutdown): Extension<ShutdownSignal>,
+    context: RequestContext,
+    limits: Option<Extension<WsLimits>>,
+    protocol: GraphQLProtocol,
+    auth: ConnectionAuth,
+    upgrade: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let limits = limits.map(|Extension(limits)| limits).unwrap_or_default();
+    let permit = limits.connect();
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |socket| async move {
+            let (sink, stream) = socket.split();
+            let operations = ActiveOperations::default();
+            let outgoing = operations.clone();
+            let mut sink = sink.with(move |message: Message| {
+                outgoing.send(&message);
+                future::ready(Ok::<_, axum::Error>(message))
+            });
+            let _permit = match permit {
+                Some(permit) => permit,
+                None => {
+                    metrics::increment_counter!(
+                        "graphql_ws_rejections_total",
+                        "reason" => "connections"
+                    );
+                    let _ = sink.send(close(close_code::AGAIN, "too many connections")).await;
+                    return;
+                }
+            };
+            metrics::increment_gauge!("graphql_ws_connections", 1.0);
+
+            let max_operations = limits.max_operations();
+            let exceeded = Arc::new(AtomicBool::new(false));
+            let over_limit = exceeded.clone();
+            let stream = stream
+                .take_while(move |message| {
+                    let within_limit = match message {
+                        Ok(message) => operations.receive(message, max_operations),
+                        Err(_) => true,
+                    };
+                    over_limit.store(!within_limit, Ordering::Relaxed);
+                    future::ready(within_limit)
+                })
+                .take_until(Box::pin(shutdown.clone().triggered()));
+            GraphQLWebSocket::new_with_pair(&mut sink, stream, schema, protocol)
+                .on_connection_init(move |payload| {
+                    future::ready(match auth.init(&payload) {
+                        Ok(auth) => {
+                            let context = RequestContext {
+                                auth: auth.clone(),
+                                ..context
+                            };
+                            let mut data = Data::default();
+                            data.insert(DataLoader::new(NoteLoader::new(store), tokio::spawn));
+                            data.insert(auth);
+                            data.insert(context);
+                            Ok(data)
+                        }
+                        Err(reason) => Err(async_graphql::Error::new(reason)),
+                    })
+                })
+                .serve()
+                .await;
+
+            if shutdown.is_triggered() {
+                let _ = sink.send(close(close_code::AWAY, "server shutting down")).await;
+            } else if exceeded.load(Ordering::Relaxed) {
+                metrics::increment_counter!(
+                    "graphql_ws_rejections_total",
+                    "reason" => "operations"
+                );
+                let _ = sink.send(close(close_code::POLICY, "too many operations")).await;
+            }
+            metrics::decrement_gauge!("graphql_ws_connections", 1.0);
+        })
+}
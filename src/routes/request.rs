@@ -0,0 +1,220 @@
+use async_graphql::http::MultipartOptions;
+use async_graphql::{BatchRequest, ParseRequestError, ServerError};
+use async_graphql_axum::{rejection::GraphQLRejection, GraphQLResponse};
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{BodyStream, FromRequest, RequestParts},
+    http::{header, Method, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use futures_util::{future, TryStreamExt};
+use std::io::{Error, ErrorKind};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::io::StreamReader;
+
+/// Extracts a GraphQL request, or a JSON array of them, from the query string
+/// (GET) or the body (JSON or multipart).
+///
+/// Unlike `async_graphql_axum::GraphQLBatchRequest` it applies the
+/// `UploadLimits` registered as a request extension, so upload limits can be
+/// configured.
+pub(crate) struct GraphQLBatchRequest(pub BatchRequest);
+
+impl GraphQLBatchRequest {
+    pub fn into_inner(self) -> BatchRequest {
+        self.0
+    }
+}
+
+/// Largest number of operations accepted in one batched request, registered
+/// as a request extension. `None` disables the limit.
+#[derive(Clone, Copy)]
+pub(crate) struct BatchLimit(pub Option<usize>);
+
+/// How long one GraphQL request may execute before it is answered with a
+/// `TIMEOUT` error, registered as a request extension.
+#[derive(Clone, Copy)]
+pub(crate) struct ExecutionTimeout(pub Duration);
+
+/// Largest accepted non-multipart request body in bytes, registered as a
+/// request extension. Multipart uploads are bounded by `UploadLimits`.
+#[derive(Clone, Copy)]
+pub(crate) struct BodyLimit(pub usize);
+
+/// Limits on multipart uploads, registered as a request extension. All are
+/// checked while the body streams in.
+#[derive(Clone, Copy)]
+pub(crate) struct UploadLimits {
+    /// Largest accepted file, in bytes.
+    pub max_file_size: usize,
+    /// Most files accepted in one request; `None` is unlimited.
+    pub max_files: Option<usize>,
+    /// Largest accepted multipart body, in bytes, however many files it holds.
+    pub max_request_size: usize,
+}
+
+impl UploadLimits {
+    fn multipart_options(&self) -> MultipartOptions {
+        let options = MultipartOptions::default().max_file_size(self.max_file_size);
+        match self.max_files {
+            Some(max_files) => options.max_num_files(max_files),
+            None => options,
+        }
+    }
+}
+
+/// Why a GraphQL request could not be read. Broken body and upload limits are
+/// answered with a GraphQL error naming the limit, anything else the way
+/// async-graphql-axum does.
+pub(crate) enum BatchRequestRejection {
+    Request(GraphQLRejection),
+    Limit { status: StatusCode, message: String },
+}
+
+impl BatchRequestRejection {
+    fn upload_limit(reason: &'static str, status: StatusCode, message: String) -> Self {
+        metrics::increment_counter!("graphql_upload_rejections_total", "reason" => reason);
+        BatchRequestRejection::Limit { status, message }
+    }
+
+    fn body_limit(limit: usize) -> Self {
+        BatchRequestRejection::Limit {
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            message: format!("request body exceeds the limit of {} bytes", limit),
+        }
+    }
+}
+
+impl From<ParseRequestError> for BatchRequestRejection {
+    fn from(err: ParseRequestError) -> Self {
+        BatchRequestRejection::Request(GraphQLRejection(err))
+    }
+}
+
+impl IntoResponse for BatchRequestRejection {
+    fn into_response(self) -> Response {
+        match self {
+            BatchRequestRejection::Request(rejection) => rejection.into_response(),
+            BatchRequestRejection::Limit { status, message } => {
+                let response =
+                    async_graphql::Response::from_errors(vec![ServerError::new(message, None)]);
+                (status, GraphQLResponse::from(response)).into_response()
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for GraphQLBatchRequest
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Into<Bytes>,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = BatchRequestRejection;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        if req.method() == Method::GET {
+            let query = req.uri().query().unwrap_or_default();
+            let request = async_graphql::http::parse_query_string(query).map_err(|err| {
+                ParseRequestError::Io(Error::new(
+                    ErrorKind::Other,
+                    format!("failed to parse graphql request from uri query: {}", err),
+                ))
+            })?;
+            return Ok(GraphQLBatchRequest(BatchRequest::Single(request)));
+        }
+
+        let upload_limits = req.extensions().get::<UploadLimits>().copied();
+        let options = upload_limits
+            .as_ref()
+            .map(UploadLimits::multipart_options)
+            .unwrap_or_default();
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string);
+        let is_multipart = content_type
+            .as_deref()
+            .map_or(false, |value| value.starts_with("multipart/"));
+        let limit = if is_multipart {
+            upload_limits.map(|limits| limits.max_request_size)
+        } else {
+            req.extensions()
+                .get::<BodyLimit>()
+                .map(|BodyLimit(limit)| *limit)
+        };
+        let declared_length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if let (Some(limit), Some(length)) = (limit, declared_length) {
+            if length > limit {
+                return Err(BatchRequestRejection::body_limit(limit));
+            }
+        }
+
+        let body = BodyStream::from_request(req).await.map_err(|_| {
+            ParseRequestError::Io(Error::new(
+                ErrorKind::Other,
+                "body has been taken by another extractor",
+            ))
+        })?;
+        // Chunked bodies carry no length, so count bytes as they arrive.
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let mut received = 0;
+        let body = body
+            .map_err(|err| Error::new(ErrorKind::Other, err))
+            .and_then({
+                let exceeded = exceeded.clone();
+                move |chunk| {
+                    received += chunk.len();
+                    future::ready(match limit {
+                        Some(limit) if received > limit => {
+                            exceeded.store(true, Ordering::Relaxed);
+                            Err(Error::new(ErrorKind::Other, "request body is too large"))
+                        }
+                        _ => Ok(chunk),
+                    })
+                }
+            });
+        let body_reader = StreamReader::new(body).compat();
+
+        match async_graphql::http::receive_batch_body(content_type, body_reader, options).await {
+            Err(_) if exceeded.load(Ordering::Relaxed) => {
+                Err(BatchRequestRejection::body_limit(limit.unwrap_or_default()))
+            }
+            Err(ParseRequestError::PayloadTooLarge) if is_multipart => match upload_limits {
+                Some(limits) => Err(BatchRequestRejection::upload_limit(
+                    "file_size",
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!(
+                        "file exceeds the upload limit of {} bytes",
+                        limits.max_file_size
+                    ),
+                )),
+                None => Err(ParseRequestError::PayloadTooLarge.into()),
+            },
+            Err(ParseRequestError::TooManyFiles) => {
+                match upload_limits.and_then(|limits| limits.max_files) {
+                    Some(max_files) => Err(BatchRequestRejection::upload_limit(
+                        "file_count",
+                        StatusCode::BAD_REQUEST,
+                        format!("request uploads more files than the limit of {}", max_files),
+                    )),
+                    None => Err(ParseRequestError::TooManyFiles.into()),
+                }
+            }
+            result => Ok(GraphQLBatchRequest(result?)),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use axum::extract::ws::Message;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Caps on `/ws`: how many connections are open at once and how many
+/// operations each of them may run.
+#[derive(Clone)]
+pub(crate) struct WsLimits {
+    connections: Arc<Semaphore>,
+    max_operations: usize,
+}
+
+impl WsLimits {
+    pub fn new(max_connections: Option<usize>, max_operations: Option<usize>) -> Self {
+        WsLimits {
+            connections: Arc::new(Semaphore::new(
+                max_connections.unwrap_or(Semaphore::MAX_PERMITS),
+            )),
+            max_operations: max_operations.unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Takes a connection slot, held until the permit is dropped, or `None`
+    /// when every slot is in use.
+    pub fn connect(&self) -> Option<OwnedSemaphorePermit> {
+        self.connections.clone().try_acquire_owned().ok()
+    }
+
+    pub fn max_operations(&self) -> usize {
+        self.max_operations
+    }
+}
+
+impl Default for WsLimits {
+    fn default() -> Self {
+        WsLimits::new(None, None)
+    }
+}
+
+/// Ids of the operations one connection has running. The incoming stream
+/// adds them and the outgoing sink drops those the server finished, so it is
+/// shared between both halves of the socket.
+#[derive(Clone, Default)]
+pub(crate) struct ActiveOperations(Arc<Mutex<HashSet<String>>>);
+
+/// The `type` and `id` every graphql-ws and graphql-transport-ws message has.
+#[derive(Deserialize)]
+struct OperationMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    id: Option<String>,
+}
+
+impl ActiveOperations {
+    /// Tracks a message from the client; returns `false` when it would start
+    /// more than `max` operations at once.
+    pub fn receive(&self, message: &Message, max: usize) -> bool {
+        let (kind, id) = match parse(message) {
+            Some(parsed) => parsed,
+            None => return true,
+        };
+        let mut ids = self.0.lock().unwrap();
+        match kind.as_str() {
+            "subscribe" | "start" if !ids.contains(&id) => {
+                if ids.len() >= max {
+                    return false;
+                }
+                ids.insert(id);
+            }
+            "complete" | "stop" => {
+                ids.remove(&id);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Forgets the operations a message from the server ends.
+    pub fn send(&self, message: &Message) {
+        if let Some((kind, id)) = parse(message) {
+            if kind == "complete" || kind == "error" {
+                self.0.lock().unwrap().remove(&id);
+            }
+        }
+    }
+}
+
+fn parse(message: &Message) -> Option<(String, String)> {
+    match message {
+        Message::Text(text) => {
+            let message: OperationMessage = serde_json::from_str(text).ok()?;
+            Some((message.kind, message.id?))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn message(kind: &str, id: &str) -> Message {
+        Message::Text(json!({ "type": kind, "id": id }).to_string())
+    }
+
+    #[test]
+    fn finished_operations_free_their_slot() {
+        let operations = ActiveOperations::default();
+
+        assert!(operations.receive(&message("subscribe", "1"), 2));
+        assert!(operations.receive(&message("subscribe", "2"), 2));
+        assert!(!operations.receive(&message("subscribe", "3"), 2));
+
+        operations.receive(&message("complete", "1"), 2);
+        assert!(operations.receive(&message("subscribe", "3"), 2));
+        operations.send(&message("complete", "2"));
+        assert!(operations.receive(&message("subscribe", "4"), 2));
+    }
+
+    #[test]
+    fn connection_slots_are_returned_on_drop() {
+        let limits = WsLimits::new(Some(1), None);
+
+        let permit = limits.connect();
+        assert!(permit.is_some());
+        assert!(limits.connect().is_none());
+        drop(permit);
+        assert!(limits.connect().is_some());
+    }
+}
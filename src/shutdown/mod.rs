@@ -0,0 +1,129 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+/// Where the server is in its life; readiness follows it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Phase {
+    Starting,
+    Ready,
+    ShuttingDown,
+}
+
+/// Owns the shutdown state; lives in `main` for the lifetime of the server.
+pub(crate) struct Shutdown {
+    notify: watch::Sender<Phase>,
+    drain_tx: mpsc::Sender<()>,
+    drain_rx: mpsc::Receiver<()>,
+}
+
+/// Cheap handle given to long-lived connections so they can react to shutdown.
+///
+/// Every live clone keeps [`Shutdown::drained`] pending, so a connection that
+/// holds one on to the end is waited for before the process exits.
+#[derive(Clone)]
+pub(crate) struct ShutdownSignal {
+    notified: watch::Receiver<Phase>,
+    _drain: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (notify, _) = watch::channel(Phase::Starting);
+        let (drain_tx, drain_rx) = mpsc::channel(1);
+        Shutdown {
+            notify,
+            drain_tx,
+            drain_rx,
+        }
+    }
+
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal {
+            notified: self.notify.subscribe(),
+            _drain: self.drain_tx.clone(),
+        }
+    }
+
+    /// Marks the server ready for traffic, unless it is already shutting down.
+    pub fn mark_ready(&self) {
+        if *self.notify.borrow() == Phase::Starting {
+            let _ = self.notify.send(Phase::Ready);
+        }
+    }
+
+    pub fn trigger(&self) {
+        let _ = self.notify.send(Phase::ShuttingDown);
+    }
+
+    /// Resolves `timeout` after shutdown is triggered. Unlike a
+    /// [`ShutdownSignal`], waiting on it doesn't hold up [`Shutdown::drained`].
+    pub fn deadline(&self, timeout: Duration) -> impl Future<Output = ()> {
+        let notified = self.notify.subscribe();
+        async move {
+            wait_for_trigger(notified).await;
+            tokio::time::sleep(timeout).await;
+        }
+    }
+
+    /// Resolves once every [`ShutdownSignal`] handed out has been dropped.
+    pub async fn drained(self) {
+        let Shutdown {
+            drain_tx,
+            mut drain_rx,
+            ..
+        } = self;
+        drop(drain_tx);
+        let _ = drain_rx.recv().await;
+    }
+}
+
+impl ShutdownSignal {
+    pub fn phase(&self) -> Phase {
+        *self.notified.borrow()
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.phase() == Phase::ShuttingDown
+    }
+
+    pub async fn triggered(self) {
+        wait_for_trigger(self.notified).await;
+    }
+}
+
+/// Counts the requests being served, so the drain deadline can tell how many
+/// it cut off.
+#[derive(Clone, Default)]
+pub(crate) struct OpenRequests(Arc<AtomicUsize>);
+
+/// One request in [`OpenRequests`], counted until dropped.
+pub(crate) struct OpenRequest(Arc<AtomicUsize>);
+
+impl OpenRequests {
+    pub fn start(&self) -> OpenRequest {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        OpenRequest(self.0.clone())
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for OpenRequest {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+async fn wait_for_trigger(mut notified: watch::Receiver<Phase>) {
+    while *notified.borrow() != Phase::ShuttingDown {
+        if notified.changed().await.is_err() {
+            // The owner is gone without triggering; nothing will ever fire.
+            std::future::pending::<()>().await;
+        }
+    }
+}